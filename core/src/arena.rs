@@ -0,0 +1,71 @@
+use bumpalo::Bump;
+
+/// Arenas kept for reuse by short-lived environments, such as the forks explored by a tree
+/// search, so each fork doesn't pay for fresh allocations.
+#[derive(Default)]
+pub struct ArenaPool {
+    free: Vec<Bump>,
+}
+
+impl ArenaPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty arena, reusing the memory of a recycled one if there is one.
+    pub fn take(&mut self) -> Bump {
+        self.free.pop().unwrap_or_default()
+    }
+
+    /// Empties `arena` and keeps its memory for a later `take`. The borrow checker ensures nothing
+    /// allocated in it (e.g. a forked environment) is still alive.
+    pub fn recycle(&mut self, mut arena: Bump) {
+        arena.reset();
+        self.free.push(arena);
+    }
+
+    /// Number of arenas waiting to be reused.
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wildfire::config::WildfireConfiguration;
+    use crate::wildfire::state::WildfireState;
+
+    #[test]
+    fn forks_are_independent_and_arenas_are_reused() {
+        let config = WildfireConfiguration::test(2, (2, 2))
+            .with_max_agents(1, 1)
+            .with_max_fires(2, 2)
+            .with_fuel(5);
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        state.env.add_fire(1, &(1, 0, 9, 1)).unwrap();
+
+        let mut pool = ArenaPool::new();
+        let fork_arena = pool.take();
+        let mut fork = state.fork(&fork_arena);
+        fork.env.add_fire(1, &(0, 0, 4, 2)).unwrap();
+        fork.env.fuel[0] = 1;
+        assert_eq!(fork.env.offsets[1], (2, 4));
+        assert_eq!(state.env.offsets[1], (2, 3));
+        assert_eq!(state.env.fuel[0], 0);
+        assert_eq!(fork.snapshot().agent, state.snapshot().agent);
+
+        let allocated = fork_arena.allocated_bytes();
+        pool.recycle(fork_arena);
+        assert_eq!(pool.len(), 1);
+        let reused = pool.take();
+        assert!(allocated > 0);
+        assert_eq!(reused.allocated_bytes(), allocated);
+        assert!(pool.is_empty());
+    }
+}
@@ -3,10 +3,34 @@ use color_eyre::Result;
 use std::collections::HashMap;
 
 use crate::config::Configuration;
-use crate::spaces::Sample;
 use crate::spaces::Space;
+use crate::spaces::{Sample, SampleBatch};
 use crate::state::State;
 
+/// Per-agent outcome of a step. Each value holds one entry per environment, indexed the same way
+/// as the `Vec<Sample>` actions passed to `step`.
+#[derive(Debug, Clone, Default)]
+pub struct StepResult {
+    pub rewards: HashMap<String, Vec<f32>>,
+    pub terminated: HashMap<String, Vec<bool>>,
+    pub truncated: HashMap<String, Vec<bool>>,
+    pub infos: HashMap<String, Vec<HashMap<String, f32>>>,
+}
+
+/// Outcome of a single environment in `step_batch`, keyed by agent.
+#[derive(Debug, Clone, Default)]
+pub struct EnvStepResult {
+    pub rewards: HashMap<String, f32>,
+    pub terminated: HashMap<String, bool>,
+    pub truncated: HashMap<String, bool>,
+    pub infos: HashMap<String, HashMap<String, f32>>,
+    /// Observation of each agent after the step.
+    pub observations: HashMap<String, Sample>,
+    /// Set when the episode ended and the environment was reset automatically: the observations
+    /// at the end of the episode, while `observations` starts the next one.
+    pub final_observations: Option<HashMap<String, Sample>>,
+}
+
 pub trait SimulatedEnvironment<'a> {
     type State: State<'a>;
     type Config: Configuration;
@@ -17,15 +41,29 @@ pub trait SimulatedEnvironment<'a> {
 
     fn state(&self) -> &Self::State;
 
-    fn step(&mut self, actions: &HashMap<String, Vec<Sample>>) -> Result<()>;
+    fn step(&mut self, actions: &HashMap<String, Vec<Sample>>) -> Result<StepResult>;
+
+    /// Steps every environment at once, taking one batch per agent with a row per environment
+    /// (e.g. from `Space::sample_batch(num_envs, rng)`) and returning one result, with
+    /// observations, per environment.
+    fn step_batch(&mut self, actions: HashMap<String, SampleBatch>) -> Result<Vec<EnvStepResult>>;
 
     fn reset(&mut self) -> Result<()>;
     fn reset_seeded(&mut self, seed: u64) -> Result<()>;
 
-    fn observe(&self, agent: &str) -> Result<()>;
+    /// Resets only environment `env_idx`, reseeding its random streams if `seed` is given.
+    fn reset_env(&mut self, env_idx: usize, seed: Option<u64>) -> Result<()>;
+
+    /// Observes `agent` in every environment, returning one sample per environment that conforms
+    /// to `observation_space(agent)`.
+    fn observe(&self, agent: &str) -> Result<Vec<Sample>>;
 
     fn action_space(&self, agent: &str) -> &dyn Space;
 
+    /// Legal actions for `agent` in every environment, one mask per environment aligned with
+    /// `action_space(agent).enumerate()`.
+    fn action_mask(&self, agent: &str) -> Result<Vec<Vec<bool>>>;
+
     fn observation_space(&self, agent: &str) -> &dyn Space;
 }
 
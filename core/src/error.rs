@@ -6,6 +6,14 @@ pub enum CoreError {
     IndexOutOfBounds(usize),
     #[error("Capacity exceeded: attempted {attempted}, max {max}")]
     CapacityExceeded { attempted: usize, max: usize },
+    #[error("Invalid sample at ${path}: {reason}")]
+    InvalidSample { path: String, reason: String },
+    #[error("Dimension mismatch: expected {expected}, got {actual}")]
+    DimensionMismatch { expected: usize, actual: usize },
+    #[error("Cardinality overflows usize")]
+    CardinalityOverflow,
+    #[error("Space has uncountably many values")]
+    Uncountable,
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
 }
@@ -1,3 +1,4 @@
+pub mod arena;
 pub mod config;
 pub mod env;
 pub mod error;
@@ -1,16 +1,95 @@
 use itertools::Itertools;
-use rand::{Rng, SeedableRng, rngs::StdRng};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::any::Any;
-use std::collections::HashMap;
-use std::fmt::Debug;
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{self, Debug, Display};
 use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+
+use crate::error::CoreError;
 
 pub trait Space: Debug + Send + Sync {
-    fn sample(&self) -> Option<Sample>;
+    /// Samples using a caller-supplied generator, so one RNG can drive all randomness.
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample>;
+
+    fn sample(&self) -> Option<Sample> {
+        self.sample_with_rng(&mut StdRng::from_entropy())
+    }
+
+    fn sample_with_seed(&self, seed: u64) -> Option<Sample> {
+        self.sample_with_rng(&mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Draws `n` samples at once, e.g. one per environment. Leaf spaces store the batch as flat
+    /// arrays instead of `n` separate samples.
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        (0..n)
+            .map(|_| self.sample_with_rng(rng))
+            .collect::<Option<Vec<_>>>()
+            .map(SampleBatch::Samples)
+    }
+
+    /// Lazily walks every value of the space, or `None` if it cannot be enumerated.
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>>;
+
+    fn enumerate(&self) -> Option<Vec<Sample>> {
+        self.enumerate_iter().map(Iterator::collect)
+    }
+
+    /// Exact number of values in the space.
+    fn cardinality(&self) -> Result<usize, CoreError>;
+
+    /// Samples uniformly from the values of `enumerate` whose entry in `mask` is true.
+    fn sample_masked_with_rng(&self, mask: &[bool], rng: &mut dyn RngCore) -> Option<Sample> {
+        choose_masked(self.enumerate()?, mask, rng)
+    }
+
+    fn sample_masked(&self, mask: &[bool]) -> Option<Sample> {
+        self.sample_masked_with_rng(mask, &mut StdRng::from_entropy())
+    }
+
+    fn sample_masked_with_seed(&self, mask: &[bool], seed: u64) -> Option<Sample> {
+        self.sample_masked_with_rng(mask, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Samples the values of `enumerate` in proportion to `weights`, which need not sum to one.
+    /// Returns `None` if the weights do not line up with the values or are all zero.
+    fn sample_weighted_with_rng(&self, weights: &[f32], rng: &mut dyn RngCore) -> Option<Sample> {
+        choose_weighted(self.enumerate()?, weights, rng)
+    }
+
+    fn sample_weighted(&self, weights: &[f32]) -> Option<Sample> {
+        self.sample_weighted_with_rng(weights, &mut StdRng::from_entropy())
+    }
+
+    fn sample_weighted_with_seed(&self, weights: &[f32], seed: u64) -> Option<Sample> {
+        self.sample_weighted_with_rng(weights, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Whether `sample` is a value of this space.
+    fn contains(&self, sample: &Sample) -> bool;
+
+    /// Like `contains`, but reports where in a nested sample the mismatch is.
+    fn validate(&self, sample: &Sample) -> Result<(), CoreError> {
+        if self.contains(sample) {
+            Ok(())
+        } else {
+            Err(invalid_sample(self, sample))
+        }
+    }
+
+    /// Length of the vectors produced by `flatten`.
+    fn flat_dim(&self) -> usize;
 
-    fn sample_with_seed(&self, seed: u64) -> Option<Sample>;
+    /// Encodes a sample as a fixed-length vector: one-hot for discrete choices, concatenation for
+    /// everything else.
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError>;
 
-    fn enumerate(&self) -> Option<Vec<Sample>>;
+    /// Inverse of `flatten`; one-hot sections decode to their largest entry.
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError>;
 
     fn len(&self) -> Option<usize> {
         None
@@ -18,48 +97,445 @@ pub trait Space: Debug + Send + Sync {
 
     fn is_empty(&self) -> bool;
 
+    /// Owned, serializable description of the space tree.
+    fn describe(&self) -> SpaceDescription;
+
+    fn kind(&self) -> SpaceKind {
+        self.describe().kind()
+    }
+
     fn as_any(&self) -> &dyn Any;
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Tagged description of a space, used to serialize `Box<dyn Space>` trees.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SpaceDescription {
+    Discrete {
+        n: i32,
+        start: i32,
+    },
+    Box {
+        low: Vec<i32>,
+        high: Vec<i32>,
+    },
+    FloatBox {
+        low: Vec<f32>,
+        high: Vec<f32>,
+        #[serde(default)]
+        shape: Option<Vec<usize>>,
+    },
+    MultiDiscrete {
+        n: Vec<i32>,
+        start: Vec<i32>,
+    },
+    MultiBinary {
+        n: usize,
+    },
+    Tuple {
+        spaces: Vec<SpaceDescription>,
+    },
+    Dict {
+        spaces: BTreeMap<String, SpaceDescription>,
+    },
+    OneOf {
+        spaces: Vec<SpaceDescription>,
+    },
+    Sequence {
+        inner: Box<SpaceDescription>,
+        max_len: usize,
+    },
+    Set {
+        inner: Box<SpaceDescription>,
+        max_len: usize,
+    },
+    Graph {
+        node_space: Box<SpaceDescription>,
+        edge_space: Box<SpaceDescription>,
+        max_nodes: usize,
+        max_edges: usize,
+    },
+}
+
+impl SpaceDescription {
+    /// Builds the space this describes.
+    pub fn build(self) -> Box<dyn Space> {
+        match self {
+            SpaceDescription::Discrete { n, start } => Box::new(DiscreteSpace { n, start }),
+            SpaceDescription::Box { low, high } => Box::new(BoxSpace { low, high }),
+            SpaceDescription::FloatBox { low, high, shape } => {
+                Box::new(FloatBoxSpace { low, high, shape })
+            }
+            SpaceDescription::MultiDiscrete { n, start } => {
+                Box::new(MultiDiscreteSpace { n, start })
+            }
+            SpaceDescription::MultiBinary { n } => Box::new(MultiBinarySpace { n }),
+            SpaceDescription::Tuple { spaces } => Box::new(TupleSpace {
+                spaces: spaces.into_iter().map(SpaceDescription::build).collect(),
+            }),
+            SpaceDescription::Dict { spaces } => Box::new(DictSpace {
+                spaces: spaces.into_iter().map(|(k, s)| (k, s.build())).collect(),
+            }),
+            SpaceDescription::OneOf { spaces } => Box::new(OneOfSpace {
+                spaces: spaces.into_iter().map(SpaceDescription::build).collect(),
+            }),
+            SpaceDescription::Sequence { inner, max_len } => Box::new(SequenceSpace {
+                inner: inner.build(),
+                max_len,
+            }),
+            SpaceDescription::Set { inner, max_len } => Box::new(SetSpace {
+                inner: inner.build(),
+                max_len,
+            }),
+            SpaceDescription::Graph {
+                node_space,
+                edge_space,
+                max_nodes,
+                max_edges,
+            } => Box::new(GraphSpace {
+                node_space: node_space.build(),
+                edge_space: edge_space.build(),
+                max_nodes,
+                max_edges,
+            }),
+        }
+    }
+}
+
+/// Which kind of space a `Box<dyn Space>` holds, without downcasting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpaceKind {
+    Discrete,
+    Box,
+    FloatBox,
+    MultiDiscrete,
+    MultiBinary,
+    Tuple,
+    Dict,
+    OneOf,
+    Sequence,
+    Set,
+    Graph,
+}
+
+impl SpaceDescription {
+    pub fn kind(&self) -> SpaceKind {
+        match self {
+            SpaceDescription::Discrete { .. } => SpaceKind::Discrete,
+            SpaceDescription::Box { .. } => SpaceKind::Box,
+            SpaceDescription::FloatBox { .. } => SpaceKind::FloatBox,
+            SpaceDescription::MultiDiscrete { .. } => SpaceKind::MultiDiscrete,
+            SpaceDescription::MultiBinary { .. } => SpaceKind::MultiBinary,
+            SpaceDescription::Tuple { .. } => SpaceKind::Tuple,
+            SpaceDescription::Dict { .. } => SpaceKind::Dict,
+            SpaceDescription::OneOf { .. } => SpaceKind::OneOf,
+            SpaceDescription::Sequence { .. } => SpaceKind::Sequence,
+            SpaceDescription::Set { .. } => SpaceKind::Set,
+            SpaceDescription::Graph { .. } => SpaceKind::Graph,
+        }
+    }
+}
+
+/// Gym-style rendering, e.g. `Tuple(Discrete(5), Box([0, 0], [3, 3]))`.
+impl Display for SpaceDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join<T: Display>(
+            f: &mut fmt::Formatter<'_>,
+            items: impl IntoIterator<Item = T>,
+        ) -> fmt::Result {
+            for (i, item) in items.into_iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{item}")?;
+            }
+            Ok(())
+        }
+
+        match self {
+            SpaceDescription::Discrete { n, start: 0 } => write!(f, "Discrete({n})"),
+            SpaceDescription::Discrete { n, start } => write!(f, "Discrete({n}, start={start})"),
+            SpaceDescription::Box { low, high } => write!(f, "Box({low:?}, {high:?})"),
+            SpaceDescription::FloatBox {
+                low,
+                high,
+                shape: None,
+            } => write!(f, "FloatBox({low:?}, {high:?})"),
+            SpaceDescription::FloatBox {
+                low,
+                high,
+                shape: Some(shape),
+            } => write!(f, "FloatBox({low:?}, {high:?}, shape={shape:?})"),
+            SpaceDescription::MultiDiscrete { n, start } if start.iter().all(|s| *s == 0) => {
+                write!(f, "MultiDiscrete({n:?})")
+            }
+            SpaceDescription::MultiDiscrete { n, start } => {
+                write!(f, "MultiDiscrete({n:?}, start={start:?})")
+            }
+            SpaceDescription::MultiBinary { n } => write!(f, "MultiBinary({n})"),
+            SpaceDescription::Tuple { spaces } => {
+                write!(f, "Tuple(")?;
+                join(f, spaces)?;
+                write!(f, ")")
+            }
+            SpaceDescription::Dict { spaces } => {
+                write!(f, "Dict(")?;
+                join(f, spaces.iter().map(|(k, s)| format!("{k:?}: {s}")))?;
+                write!(f, ")")
+            }
+            SpaceDescription::OneOf { spaces } => {
+                write!(f, "OneOf(")?;
+                join(f, spaces)?;
+                write!(f, ")")
+            }
+            SpaceDescription::Sequence { inner, max_len } => {
+                write!(f, "Sequence({inner}, max_len={max_len})")
+            }
+            SpaceDescription::Set { inner, max_len } => {
+                write!(f, "Set({inner}, max_len={max_len})")
+            }
+            SpaceDescription::Graph {
+                node_space,
+                edge_space,
+                max_nodes,
+                max_edges,
+            } => write!(
+                f,
+                "Graph({node_space}, {edge_space}, max_nodes={max_nodes}, max_edges={max_edges})"
+            ),
+        }
+    }
+}
+
+/// Spaces are equal when they describe the same tree, whatever their concrete types.
+impl PartialEq for dyn Space {
+    fn eq(&self, other: &Self) -> bool {
+        self.describe() == other.describe()
+    }
+}
+
+impl Display for dyn Space {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.describe(), f)
+    }
+}
+
+impl Serialize for dyn Space {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.describe().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Box<dyn Space> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        SpaceDescription::deserialize(deserializer).map(SpaceDescription::build)
+    }
+}
+
+fn invalid_sample<S: Space + ?Sized>(space: &S, sample: &Sample) -> CoreError {
+    CoreError::InvalidSample {
+        path: String::new(),
+        reason: format!("{sample:?} is not contained in {space:?}"),
+    }
+}
+
+fn prefix_path(error: CoreError, segment: &str) -> CoreError {
+    match error {
+        CoreError::InvalidSample { path, reason } => CoreError::InvalidSample {
+            path: format!("{segment}{path}"),
+            reason,
+        },
+        other => other,
+    }
+}
+
+/// Lazy cartesian product of the values of several spaces, varying the last space fastest.
+///
+/// Exhausted positions are restarted by enumerating their space again, so nothing beyond the
+/// current combination is held in memory.
+struct Product<'a> {
+    spaces: Vec<&'a dyn Space>,
+    iters: Vec<Box<dyn Iterator<Item = Sample> + 'a>>,
+    current: Option<Vec<Sample>>,
+    done: bool,
+}
+
+impl<'a> Product<'a> {
+    fn new(spaces: Vec<&'a dyn Space>) -> Option<Self> {
+        let iters = spaces
+            .iter()
+            .map(|s| s.enumerate_iter())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Product {
+            spaces,
+            iters,
+            current: None,
+            done: false,
+        })
+    }
+
+    fn advance(&mut self) -> Option<Vec<Sample>> {
+        let Some(current) = self.current.as_mut() else {
+            let first = self
+                .iters
+                .iter_mut()
+                .map(|iter| iter.next())
+                .collect::<Option<Vec<_>>>()?;
+            self.current = Some(first.clone());
+            return Some(first);
+        };
+
+        for i in (0..self.iters.len()).rev() {
+            if let Some(sample) = self.iters[i].next() {
+                current[i] = sample;
+                return Some(current.clone());
+            }
+
+            let mut restarted = self.spaces[i].enumerate_iter()?;
+            current[i] = restarted.next()?;
+            self.iters[i] = restarted;
+        }
+        None
+    }
+}
+
+impl Iterator for Product<'_> {
+    type Item = Vec<Sample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.advance();
+        self.done = next.is_none();
+        next
+    }
+}
+
+fn check_dim(expected: usize, flat: &[f32]) -> Result<(), CoreError> {
+    if flat.len() == expected {
+        Ok(())
+    } else {
+        Err(CoreError::DimensionMismatch {
+            expected,
+            actual: flat.len(),
+        })
+    }
+}
+
+fn invalid_flat<S: Space + ?Sized>(space: &S) -> CoreError {
+    CoreError::InvalidSample {
+        path: String::new(),
+        reason: format!("empty flat vector for {space:?}"),
+    }
+}
+
+fn argmax(values: &[f32]) -> Option<usize> {
+    values
+        .iter()
+        .enumerate()
+        .fold(None, |best: Option<(usize, f32)>, (i, &v)| match best {
+            Some((_, b)) if b >= v => best,
+            _ => Some((i, v)),
+        })
+        .map(|(i, _)| i)
+}
+
+fn choose_masked(samples: Vec<Sample>, mask: &[bool], rng: &mut dyn RngCore) -> Option<Sample> {
+    let mut allowed: Vec<Sample> = samples
+        .into_iter()
+        .zip(mask)
+        .filter(|(_, allowed)| **allowed)
+        .map(|(sample, _)| sample)
+        .collect();
+    if allowed.is_empty() {
+        return None;
+    }
+
+    let index = rng.gen_range(0..allowed.len());
+    Some(allowed.swap_remove(index))
+}
+
+fn choose_weighted(
+    mut samples: Vec<Sample>,
+    weights: &[f32],
+    rng: &mut dyn RngCore,
+) -> Option<Sample> {
+    if samples.len() != weights.len() {
+        return None;
+    }
+
+    let index = WeightedIndex::new(weights).ok()?.sample(rng);
+    Some(samples.swap_remove(index))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DiscreteSpace {
     pub n: i32,
     pub start: i32,
 }
 
 impl Space for DiscreteSpace {
-    fn sample(&self) -> Option<Sample> {
-        if self.n == 0 {
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        if self.is_empty() {
             return None;
         }
 
-        let mut rng = StdRng::from_entropy();
         Some(Sample::Discrete(
             rng.gen_range(self.start..(self.start + self.n)),
         ))
     }
 
-    fn sample_with_seed(&self, seed: u64) -> Option<Sample> {
-        if self.n == 0 {
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        if self.is_empty() && n > 0 {
             return None;
         }
 
-        let mut rng = StdRng::seed_from_u64(seed);
-        Some(Sample::Discrete(
-            rng.gen_range(self.start..(self.start + self.n)),
+        Some(SampleBatch::Discrete(
+            (0..n)
+                .map(|_| rng.gen_range(self.start..(self.start + self.n)))
+                .collect(),
         ))
     }
 
-    fn enumerate(&self) -> Option<Vec<Sample>> {
-        if self.n == 0 {
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        if self.is_empty() {
             return None;
         }
 
-        Some(
-            (0..self.n)
-                .map(|i| Sample::Discrete(i + self.start))
-                .collect(),
-        )
+        Some(Box::new(
+            (0..self.n).map(|i| Sample::Discrete(i + self.start)),
+        ))
+    }
+
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        Ok(self.n.max(0) as usize)
+    }
+
+    fn contains(&self, sample: &Sample) -> bool {
+        matches!(sample, Sample::Discrete(v) if (self.start..self.start + self.n).contains(v))
+    }
+
+    fn flat_dim(&self) -> usize {
+        self.n.max(0) as usize
+    }
+
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        match sample {
+            Sample::Discrete(v) if self.contains(sample) => {
+                let mut flat = vec![0.0; self.flat_dim()];
+                flat[(v - self.start) as usize] = 1.0;
+                Ok(flat)
+            }
+            _ => Err(invalid_sample(self, sample)),
+        }
+    }
+
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        let index = argmax(flat).ok_or_else(|| invalid_flat(self))?;
+        Ok(Sample::Discrete(self.start + index as i32))
     }
 
     fn len(&self) -> Option<usize> {
@@ -67,7 +543,14 @@ impl Space for DiscreteSpace {
     }
 
     fn is_empty(&self) -> bool {
-        self.n == 0
+        self.n <= 0
+    }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::Discrete {
+            n: self.n,
+            start: self.start,
+        }
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
@@ -75,19 +558,18 @@ impl Space for DiscreteSpace {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BoxSpace {
     pub low: Vec<i32>,
     pub high: Vec<i32>,
 }
 
 impl Space for BoxSpace {
-    fn sample(&self) -> Option<Sample> {
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
         if self.low.is_empty() || self.high.is_empty() {
             return None;
         }
 
-        let mut rng = StdRng::from_entropy();
         Some(Sample::Box(
             self.low
                 .iter()
@@ -97,22 +579,25 @@ impl Space for BoxSpace {
         ))
     }
 
-    fn sample_with_seed(&self, seed: u64) -> Option<Sample> {
-        if self.low.is_empty() || self.high.is_empty() {
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        if (self.low.is_empty() || self.high.is_empty()) && n > 0 {
             return None;
         }
 
-        let mut rng = StdRng::seed_from_u64(seed);
-        Some(Sample::Box(
-            self.low
-                .iter()
-                .zip(self.high.iter())
-                .map(|(l, h)| rng.gen_range(*l..=*h))
-                .collect(),
-        ))
+        let dim = self.low.len().min(self.high.len());
+        let mut values = Vec::with_capacity(n * dim);
+        for _ in 0..n {
+            values.extend(
+                self.low
+                    .iter()
+                    .zip(self.high.iter())
+                    .map(|(l, h)| rng.gen_range(*l..=*h)),
+            );
+        }
+        Some(SampleBatch::Box { values, dim })
     }
 
-    fn enumerate(&self) -> Option<Vec<Sample>> {
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
         if self.low.is_empty() || self.high.is_empty() {
             return None;
         }
@@ -121,244 +606,1325 @@ impl Space for BoxSpace {
             return None;
         }
 
-        let ranges: Vec<Vec<i32>> = self
+        let ranges: Vec<RangeInclusive<i32>> = self
             .low
             .iter()
             .zip(self.high.iter())
-            .map(|(l, h)| (*l..=*h).collect::<Vec<i32>>())
+            .map(|(l, h)| *l..=*h)
             .collect();
         if ranges.iter().any(|r| r.is_empty()) {
             return None;
         }
 
-        let product = ranges
-            .iter()
-            .map(|r| r.iter().cloned())
-            .multi_cartesian_product();
-        Some(product.map(Sample::Box).collect())
+        Some(Box::new(
+            ranges
+                .into_iter()
+                .multi_cartesian_product()
+                .map(Sample::Box),
+        ))
     }
 
-    fn len(&self) -> Option<usize> {
-        Some(self.low.len())
-    }
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        if self.low.len() != self.high.len() || self.low.is_empty() {
+            return Ok(0);
+        }
 
-    fn is_empty(&self) -> bool {
-        self.low.is_empty() || self.high.is_empty()
+        self.low
+            .iter()
+            .zip(self.high.iter())
+            .map(|(l, h)| (*h as i64 - *l as i64 + 1).max(0) as usize)
+            .try_fold(1usize, |acc, n| acc.checked_mul(n))
+            .ok_or(CoreError::CardinalityOverflow)
     }
 
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
+    fn contains(&self, sample: &Sample) -> bool {
+        let Sample::Box(values) = sample else {
+            return false;
+        };
+        values.len() == self.low.len()
+            && self.low.len() == self.high.len()
+            && values
+                .iter()
+                .zip(self.low.iter().zip(self.high.iter()))
+                .all(|(v, (l, h))| (l..=h).contains(&v))
     }
-}
 
-#[derive(Debug)]
-pub struct TupleSpace {
-    pub spaces: Vec<Box<dyn Space>>,
-}
-
-impl Space for TupleSpace {
-    fn sample(&self) -> Option<Sample> {
-        let mut samples = Vec::with_capacity(self.spaces.len());
-        for s in &self.spaces {
-            match s.sample() {
-                Some(sample) => samples.push(sample),
-                None => return None,
-            }
-        }
-        Some(Sample::Tuple(samples))
+    fn flat_dim(&self) -> usize {
+        self.low.len()
     }
-    fn sample_with_seed(&self, seed: u64) -> Option<Sample> {
-        let mut samples = Vec::with_capacity(self.spaces.len());
-        for (i, s) in self.spaces.iter().enumerate() {
-            match s.sample_with_seed(seed + i as u64) {
-                Some(sample) => samples.push(sample),
-                None => return None,
+
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        match sample {
+            Sample::Box(values) if self.contains(sample) => {
+                Ok(values.iter().map(|&v| v as f32).collect())
             }
+            _ => Err(invalid_sample(self, sample)),
         }
-        Some(Sample::Tuple(samples))
     }
-    fn enumerate(&self) -> Option<Vec<Sample>> {
-        let enumerations: Vec<Option<Vec<Sample>>> =
-            self.spaces.iter().map(|s| s.enumerate()).collect();
-        if enumerations.iter().any(|e| e.is_none()) {
-            return None;
-        }
-        let enumerated: Vec<Vec<Sample>> = enumerations.into_iter().map(|e| e.unwrap()).collect();
-        let product = enumerated
-            .iter()
-            .map(|v| v.iter().cloned())
-            .multi_cartesian_product();
-        Some(product.map(Sample::Tuple).collect())
+
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        Ok(Sample::Box(
+            flat.iter().map(|&v| v.round() as i32).collect(),
+        ))
     }
+
     fn len(&self) -> Option<usize> {
-        Some(self.spaces.len())
+        Some(self.low.len())
     }
+
     fn is_empty(&self) -> bool {
-        self.spaces.is_empty()
+        self.low.is_empty() || self.high.is_empty()
+    }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::Box {
+            low: self.low.clone(),
+            high: self.high.clone(),
+        }
     }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 }
 
-#[derive(Debug)]
-pub struct DictSpace {
-    pub spaces: HashMap<String, Box<dyn Space>>,
+/// A continuous box with inclusive `f32` bounds per dimension.
+///
+/// `shape`, when set, describes how the flat `low`/`high` values are laid out (row-major) and must
+/// multiply out to their length.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FloatBoxSpace {
+    pub low: Vec<f32>,
+    pub high: Vec<f32>,
+    pub shape: Option<Vec<usize>>,
 }
 
-impl Space for DictSpace {
-    fn sample(&self) -> Option<Sample> {
-        let mut dict = HashMap::new();
-        for (k, s) in &self.spaces {
-            match s.sample() {
-                Some(sample) => {
-                    dict.insert(k.clone(), sample);
-                }
-                None => return None,
-            }
-        }
-        Some(Sample::Dict(dict))
+impl FloatBoxSpace {
+    /// Layout of samples, defaulting to a single flat dimension.
+    pub fn shape(&self) -> Vec<usize> {
+        self.shape.clone().unwrap_or_else(|| vec![self.low.len()])
     }
+}
 
-    fn sample_with_seed(&self, seed: u64) -> Option<Sample> {
-        let mut dict = HashMap::new();
-        for (i, (k, s)) in self.spaces.iter().enumerate() {
-            match s.sample_with_seed(seed + i as u64) {
-                Some(sample) => {
-                    dict.insert(k.clone(), sample);
-                }
-                None => return None,
-            }
+impl Space for FloatBoxSpace {
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        if self.is_empty() {
+            return None;
         }
-        Some(Sample::Dict(dict))
+
+        Some(Sample::FloatBox(
+            self.low
+                .iter()
+                .zip(self.high.iter())
+                .map(|(l, h)| rng.gen_range(*l..=*h))
+                .collect(),
+        ))
     }
 
-    fn enumerate(&self) -> Option<Vec<Sample>> {
-        let keys: Vec<_> = self.spaces.keys().cloned().collect();
-        let enumerations: Vec<Option<Vec<Sample>>> = keys
-            .iter()
-            .map(|key| self.spaces[key].enumerate())
-            .collect();
-        if enumerations.iter().any(|e| e.is_none()) {
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        if self.is_empty() && n > 0 {
             return None;
         }
 
-        let enumerated: Vec<Vec<Sample>> = enumerations.into_iter().map(|e| e.unwrap()).collect();
-        let product = enumerated
-            .iter()
-            .map(|v| v.iter().cloned())
-            .multi_cartesian_product();
-
-        Some(
-            product
-                .map(|samples| {
-                    let mut dict = HashMap::new();
-                    for (k, v) in keys.iter().zip(samples.into_iter()) {
-                        dict.insert(k.clone(), v);
-                    }
-                    Sample::Dict(dict)
-                })
-                .collect(),
-        )
+        let dim = self.low.len();
+        let mut values = Vec::with_capacity(n * dim);
+        for _ in 0..n {
+            values.extend(
+                self.low
+                    .iter()
+                    .zip(self.high.iter())
+                    .map(|(l, h)| rng.gen_range(*l..=*h)),
+            );
+        }
+        Some(SampleBatch::FloatBox { values, dim })
     }
 
-    fn len(&self) -> Option<usize> {
-        Some(self.spaces.len())
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        None
     }
 
-    fn is_empty(&self) -> bool {
-        self.spaces.is_empty()
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        Err(CoreError::Uncountable)
     }
 
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
+    fn contains(&self, sample: &Sample) -> bool {
+        let Sample::FloatBox(values) = sample else {
+            return false;
+        };
+        !self.is_empty()
+            && values.len() == self.low.len()
+            && values
+                .iter()
+                .zip(self.low.iter().zip(self.high.iter()))
+                .all(|(v, (l, h))| (l..=h).contains(&v))
     }
-}
 
-#[derive(Debug)]
+    fn flat_dim(&self) -> usize {
+        self.low.len()
+    }
+
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        match sample {
+            Sample::FloatBox(values) if self.contains(sample) => Ok(values.clone()),
+            _ => Err(invalid_sample(self, sample)),
+        }
+    }
+
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        Ok(Sample::FloatBox(flat.to_vec()))
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.low.len())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.low.is_empty()
+            || self.low.len() != self.high.len()
+            || self
+                .shape
+                .as_ref()
+                .is_some_and(|shape| shape.iter().product::<usize>() != self.low.len())
+    }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::FloatBox {
+            low: self.low.clone(),
+            high: self.high.clone(),
+            shape: self.shape.clone(),
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Several independent discrete choices; dimension `i` takes values in
+/// `start[i]..start[i] + n[i]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiDiscreteSpace {
+    pub n: Vec<i32>,
+    pub start: Vec<i32>,
+}
+
+impl Space for MultiDiscreteSpace {
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(Sample::MultiDiscrete(
+            self.n
+                .iter()
+                .zip(self.start.iter())
+                .map(|(n, start)| rng.gen_range(*start..(*start + *n)))
+                .collect(),
+        ))
+    }
+
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        if self.is_empty() && n > 0 {
+            return None;
+        }
+
+        let dim = self.n.len();
+        let mut values = Vec::with_capacity(n * dim);
+        for _ in 0..n {
+            values.extend(
+                self.n
+                    .iter()
+                    .zip(self.start.iter())
+                    .map(|(n, start)| rng.gen_range(*start..(*start + *n))),
+            );
+        }
+        Some(SampleBatch::MultiDiscrete { values, dim })
+    }
+
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(Box::new(
+            self.n
+                .iter()
+                .zip(self.start.iter())
+                .map(|(n, start)| *start..(*start + *n))
+                .multi_cartesian_product()
+                .map(Sample::MultiDiscrete),
+        ))
+    }
+
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+
+        self.n
+            .iter()
+            .try_fold(1usize, |acc, n| acc.checked_mul(*n as usize))
+            .ok_or(CoreError::CardinalityOverflow)
+    }
+
+    fn contains(&self, sample: &Sample) -> bool {
+        let Sample::MultiDiscrete(values) = sample else {
+            return false;
+        };
+        !self.is_empty()
+            && values.len() == self.n.len()
+            && values
+                .iter()
+                .zip(self.n.iter().zip(self.start.iter()))
+                .all(|(v, (n, start))| (*start..*start + *n).contains(v))
+    }
+
+    fn flat_dim(&self) -> usize {
+        self.n.iter().map(|n| (*n).max(0) as usize).sum()
+    }
+
+    /// Concatenates one one-hot vector per dimension.
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        match sample {
+            Sample::MultiDiscrete(values) if self.contains(sample) => {
+                let mut flat = vec![0.0; self.flat_dim()];
+                let mut offset = 0;
+                for ((v, n), start) in values.iter().zip(&self.n).zip(&self.start) {
+                    flat[offset + (v - start) as usize] = 1.0;
+                    offset += *n as usize;
+                }
+                Ok(flat)
+            }
+            _ => Err(invalid_sample(self, sample)),
+        }
+    }
+
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        let mut rest = flat;
+        let mut values = Vec::with_capacity(self.n.len());
+        for (n, start) in self.n.iter().zip(&self.start) {
+            let (head, tail) = rest.split_at((*n).max(0) as usize);
+            let index = argmax(head).ok_or_else(|| invalid_flat(self))?;
+            values.push(start + index as i32);
+            rest = tail;
+        }
+        Ok(Sample::MultiDiscrete(values))
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.n.len())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.n.is_empty() || self.n.len() != self.start.len() || self.n.iter().any(|n| *n <= 0)
+    }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::MultiDiscrete {
+            n: self.n.clone(),
+            start: self.start.clone(),
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// `n` independent on/off flags.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiBinarySpace {
+    pub n: usize,
+}
+
+impl Space for MultiBinarySpace {
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        if self.n == 0 {
+            return None;
+        }
+
+        Some(Sample::MultiBinary(
+            (0..self.n).map(|_| rng.gen_bool(0.5)).collect(),
+        ))
+    }
+
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        if self.n == 0 && n > 0 {
+            return None;
+        }
+
+        Some(SampleBatch::MultiBinary {
+            values: (0..n * self.n).map(|_| rng.gen_bool(0.5)).collect(),
+            dim: self.n,
+        })
+    }
+
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        if self.n == 0 {
+            return None;
+        }
+
+        Some(Box::new(
+            (0..self.n)
+                .map(|_| [false, true])
+                .multi_cartesian_product()
+                .map(Sample::MultiBinary),
+        ))
+    }
+
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        if self.n == 0 {
+            return Ok(0);
+        }
+
+        u32::try_from(self.n)
+            .ok()
+            .and_then(|n| 2usize.checked_pow(n))
+            .ok_or(CoreError::CardinalityOverflow)
+    }
+
+    fn contains(&self, sample: &Sample) -> bool {
+        matches!(sample, Sample::MultiBinary(values) if self.n > 0 && values.len() == self.n)
+    }
+
+    fn flat_dim(&self) -> usize {
+        self.n
+    }
+
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        match sample {
+            Sample::MultiBinary(values) if self.contains(sample) => {
+                Ok(values.iter().map(|&v| if v { 1.0 } else { 0.0 }).collect())
+            }
+            _ => Err(invalid_sample(self, sample)),
+        }
+    }
+
+    /// Entries above one half decode to `true`.
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        Ok(Sample::MultiBinary(flat.iter().map(|&v| v > 0.5).collect()))
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.n)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::MultiBinary { n: self.n }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TupleSpace {
+    pub spaces: Vec<Box<dyn Space>>,
+}
+
+impl Space for TupleSpace {
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        let mut samples = Vec::with_capacity(self.spaces.len());
+        for s in &self.spaces {
+            match s.sample_with_rng(rng) {
+                Some(sample) => samples.push(sample),
+                None => return None,
+            }
+        }
+        Some(Sample::Tuple(samples))
+    }
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        let fields = self
+            .spaces
+            .iter()
+            .map(|s| s.sample_batch(n, rng))
+            .collect::<Option<Vec<_>>>()?;
+        Some(SampleBatch::Tuple { len: n, fields })
+    }
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        let product = Product::new(self.spaces.iter().map(|s| s.as_ref()).collect())?;
+        Some(Box::new(product.map(Sample::Tuple)))
+    }
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        self.spaces.iter().try_fold(1usize, |acc, s| {
+            acc.checked_mul(s.cardinality()?)
+                .ok_or(CoreError::CardinalityOverflow)
+        })
+    }
+    fn contains(&self, sample: &Sample) -> bool {
+        let Sample::Tuple(samples) = sample else {
+            return false;
+        };
+        samples.len() == self.spaces.len()
+            && self.spaces.iter().zip(samples).all(|(s, v)| s.contains(v))
+    }
+    fn validate(&self, sample: &Sample) -> Result<(), CoreError> {
+        let Sample::Tuple(samples) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+        if samples.len() != self.spaces.len() {
+            return Err(CoreError::InvalidSample {
+                path: String::new(),
+                reason: format!(
+                    "expected {} elements, got {}",
+                    self.spaces.len(),
+                    samples.len()
+                ),
+            });
+        }
+        for (i, (s, v)) in self.spaces.iter().zip(samples).enumerate() {
+            s.validate(v)
+                .map_err(|e| prefix_path(e, &format!("[{i}]")))?;
+        }
+        Ok(())
+    }
+    fn flat_dim(&self) -> usize {
+        self.spaces.iter().map(|s| s.flat_dim()).sum()
+    }
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        self.validate(sample)?;
+        let Sample::Tuple(samples) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+
+        let mut flat = Vec::with_capacity(self.flat_dim());
+        for (s, v) in self.spaces.iter().zip(samples) {
+            flat.extend(s.flatten(v)?);
+        }
+        Ok(flat)
+    }
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        let mut rest = flat;
+        let mut samples = Vec::with_capacity(self.spaces.len());
+        for s in &self.spaces {
+            let (head, tail) = rest.split_at(s.flat_dim());
+            samples.push(s.unflatten(head)?);
+            rest = tail;
+        }
+        Ok(Sample::Tuple(samples))
+    }
+    fn len(&self) -> Option<usize> {
+        Some(self.spaces.len())
+    }
+    fn is_empty(&self) -> bool {
+        self.spaces.is_empty()
+    }
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::Tuple {
+            spaces: self.spaces.iter().map(|s| s.describe()).collect(),
+        }
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Sub-spaces are kept sorted by key, so sampling, enumeration and flattening visit them in the
+/// same order on every run.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DictSpace {
+    pub spaces: BTreeMap<String, Box<dyn Space>>,
+}
+
+impl Space for DictSpace {
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        let mut dict = BTreeMap::new();
+        for (k, s) in &self.spaces {
+            match s.sample_with_rng(rng) {
+                Some(sample) => {
+                    dict.insert(k.clone(), sample);
+                }
+                None => return None,
+            }
+        }
+        Some(Sample::Dict(dict))
+    }
+
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        let fields = self
+            .spaces
+            .iter()
+            .map(|(k, s)| Some((k.clone(), s.sample_batch(n, rng)?)))
+            .collect::<Option<BTreeMap<_, _>>>()?;
+        Some(SampleBatch::Dict { len: n, fields })
+    }
+
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        let product = Product::new(self.spaces.values().map(|s| s.as_ref()).collect())?;
+        Some(Box::new(product.map(|samples| {
+            Sample::Dict(self.spaces.keys().cloned().zip(samples).collect())
+        })))
+    }
+
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        self.spaces.values().try_fold(1usize, |acc, s| {
+            acc.checked_mul(s.cardinality()?)
+                .ok_or(CoreError::CardinalityOverflow)
+        })
+    }
+
+    fn contains(&self, sample: &Sample) -> bool {
+        let Sample::Dict(dict) = sample else {
+            return false;
+        };
+        dict.len() == self.spaces.len()
+            && self
+                .spaces
+                .iter()
+                .all(|(k, s)| dict.get(k).is_some_and(|v| s.contains(v)))
+    }
+
+    fn validate(&self, sample: &Sample) -> Result<(), CoreError> {
+        let Sample::Dict(dict) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+        if let Some(key) = dict.keys().find(|k| !self.spaces.contains_key(*k)) {
+            return Err(CoreError::InvalidSample {
+                path: String::new(),
+                reason: format!("unexpected key {key:?}"),
+            });
+        }
+        for (k, s) in &self.spaces {
+            let Some(v) = dict.get(k) else {
+                return Err(CoreError::InvalidSample {
+                    path: String::new(),
+                    reason: format!("missing key {k:?}"),
+                });
+            };
+            s.validate(v)
+                .map_err(|e| prefix_path(e, &format!("[{k:?}]")))?;
+        }
+        Ok(())
+    }
+
+    fn flat_dim(&self) -> usize {
+        self.spaces.values().map(|s| s.flat_dim()).sum()
+    }
+
+    /// Concatenates the flattened values in key order.
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        self.validate(sample)?;
+        let Sample::Dict(dict) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+
+        let mut flat = Vec::with_capacity(self.flat_dim());
+        for key in self.spaces.keys() {
+            flat.extend(self.spaces[key].flatten(&dict[key])?);
+        }
+        Ok(flat)
+    }
+
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        let mut rest = flat;
+        let mut dict = BTreeMap::new();
+        for key in self.spaces.keys() {
+            let space = &self.spaces[key];
+            let (head, tail) = rest.split_at(space.flat_dim());
+            dict.insert(key.clone(), space.unflatten(head)?);
+            rest = tail;
+        }
+        Ok(Sample::Dict(dict))
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.spaces.len())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.spaces.is_empty()
+    }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::Dict {
+            spaces: self
+                .spaces
+                .iter()
+                .map(|(k, s)| (k.clone(), s.describe()))
+                .collect(),
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct OneOfSpace {
     pub spaces: Vec<Box<dyn Space>>,
 }
 
-impl Space for OneOfSpace {
-    fn sample(&self) -> Option<Sample> {
-        let valid_spaces: Vec<_> = self
-            .spaces
-            .iter()
-            .enumerate()
-            .filter(|(_, s)| {
-                if let Some(ds) = s.as_any().downcast_ref::<DiscreteSpace>() {
-                    ds.n > 0
-                } else {
-                    true
+impl OneOfSpace {
+    /// Picks a branch in proportion to `weights` (one per sub-space, not necessarily summing to
+    /// one), then samples it uniformly. Branches that cannot produce a sample are never picked;
+    /// returns `None` if no branch with positive weight remains.
+    pub fn sample_branch_weighted_with_rng(
+        &self,
+        weights: &[f32],
+        rng: &mut dyn RngCore,
+    ) -> Option<Sample> {
+        if weights.len() != self.spaces.len() {
+            return None;
+        }
+
+        let valid_spaces = self.valid_spaces();
+        let index = WeightedIndex::new(valid_spaces.iter().map(|(i, _)| weights[*i]))
+            .ok()?
+            .sample(rng);
+        let (branch, sub_space) = valid_spaces[index];
+        sub_space
+            .sample_with_rng(rng)
+            .map(|sample| Sample::OneOf(branch as i32, Box::new(sample)))
+    }
+
+    pub fn sample_branch_weighted(&self, weights: &[f32]) -> Option<Sample> {
+        self.sample_branch_weighted_with_rng(weights, &mut StdRng::from_entropy())
+    }
+
+    pub fn sample_branch_weighted_with_seed(&self, weights: &[f32], seed: u64) -> Option<Sample> {
+        self.sample_branch_weighted_with_rng(weights, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Sub-spaces that can produce a sample, with their branch index.
+    fn valid_spaces(&self) -> Vec<(usize, &dyn Space)> {
+        self.spaces
+            .iter()
+            .map(|s| s.as_ref())
+            .enumerate()
+            .filter(|(_, s)| !s.is_empty())
+            .collect()
+    }
+}
+
+impl Space for OneOfSpace {
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        let valid_spaces = self.valid_spaces();
+        if valid_spaces.is_empty() {
+            return None;
+        }
+
+        let (index, sub_space) = valid_spaces[rng.gen_range(0..valid_spaces.len())];
+        sub_space
+            .sample_with_rng(rng)
+            .map(|sample| Sample::OneOf(index as i32, Box::new(sample)))
+    }
+
+    /// Picks every branch first, then samples each sub-space once for all of its rows.
+    fn sample_batch(&self, n: usize, rng: &mut dyn RngCore) -> Option<SampleBatch> {
+        let valid_spaces = self.valid_spaces();
+        if valid_spaces.is_empty() && n > 0 {
+            return None;
+        }
+
+        let branches: Vec<i32> = (0..n)
+            .map(|_| valid_spaces[rng.gen_range(0..valid_spaces.len())].0 as i32)
+            .collect();
+        let batches = self
+            .spaces
+            .iter()
+            .enumerate()
+            .map(|(idx, s)| {
+                let count = branches.iter().filter(|&&b| b == idx as i32).count();
+                s.sample_batch(count, rng)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(SampleBatch::OneOf { branches, batches })
+    }
+
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        let branches: Vec<_> = self
+            .spaces
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, s)| {
+                s.enumerate_iter().map(|samples| {
+                    samples.map(move |sample| Sample::OneOf(idx as i32, Box::new(sample)))
+                })
+            })
+            .collect();
+        if branches.is_empty() {
+            None
+        } else {
+            Some(Box::new(branches.into_iter().flatten()))
+        }
+    }
+    /// Counts the values of `enumerate_iter`, which skips branches that cannot be enumerated. If
+    /// no branch can be, reports why the first one cannot be counted.
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        let (enumerable, rest): (Vec<_>, Vec<_>) = self
+            .spaces
+            .iter()
+            .partition(|s| s.enumerate_iter().is_some());
+        if enumerable.is_empty() {
+            return rest
+                .iter()
+                .map(|s| s.cardinality())
+                .find(Result::is_err)
+                .unwrap_or(Ok(0));
+        }
+
+        enumerable.iter().try_fold(0usize, |acc, s| {
+            acc.checked_add(s.cardinality()?)
+                .ok_or(CoreError::CardinalityOverflow)
+        })
+    }
+    fn contains(&self, sample: &Sample) -> bool {
+        let Sample::OneOf(index, inner) = sample else {
+            return false;
+        };
+        usize::try_from(*index)
+            .ok()
+            .and_then(|i| self.spaces.get(i))
+            .is_some_and(|s| s.contains(inner))
+    }
+    fn validate(&self, sample: &Sample) -> Result<(), CoreError> {
+        let Sample::OneOf(index, inner) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+        let Some(space) = usize::try_from(*index)
+            .ok()
+            .and_then(|i| self.spaces.get(i))
+        else {
+            return Err(CoreError::InvalidSample {
+                path: String::new(),
+                reason: format!(
+                    "branch {index} out of range for {} spaces",
+                    self.spaces.len()
+                ),
+            });
+        };
+        space
+            .validate(inner)
+            .map_err(|e| prefix_path(e, &format!("<{index}>")))
+    }
+    /// A one-hot branch index followed by the flattened sub-sample, zero-padded to the widest
+    /// sub-space.
+    fn flat_dim(&self) -> usize {
+        self.spaces.len() + self.spaces.iter().map(|s| s.flat_dim()).max().unwrap_or(0)
+    }
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        self.validate(sample)?;
+        let Sample::OneOf(index, inner) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+
+        let mut flat = vec![0.0; self.flat_dim()];
+        flat[*index as usize] = 1.0;
+        let sub_flat = self.spaces[*index as usize].flatten(inner)?;
+        let offset = self.spaces.len();
+        flat[offset..offset + sub_flat.len()].copy_from_slice(&sub_flat);
+        Ok(flat)
+    }
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        let (branches, rest) = flat.split_at(self.spaces.len());
+        let index = argmax(branches).ok_or_else(|| invalid_flat(self))?;
+        let space = &self.spaces[index];
+        let inner = space.unflatten(&rest[..space.flat_dim()])?;
+        Ok(Sample::OneOf(index as i32, Box::new(inner)))
+    }
+    fn len(&self) -> Option<usize> {
+        Some(self.spaces.len())
+    }
+    fn is_empty(&self) -> bool {
+        self.spaces.is_empty()
+    }
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::OneOf {
+            spaces: self.spaces.iter().map(|s| s.describe()).collect(),
+        }
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Flattens a variable-length collection as `max_len` slots, each a presence flag followed by the
+/// flattened element; unused slots are all zeros.
+fn flatten_slots(
+    inner: &dyn Space,
+    max_len: usize,
+    items: &[Sample],
+) -> Result<Vec<f32>, CoreError> {
+    let slot_dim = 1 + inner.flat_dim();
+    let mut flat = vec![0.0; max_len * slot_dim];
+    for (slot, item) in flat.chunks_mut(slot_dim).zip(items) {
+        slot[0] = 1.0;
+        slot[1..].copy_from_slice(&inner.flatten(item)?);
+    }
+    Ok(flat)
+}
+
+/// Inverse of `flatten_slots`; decoding stops at the first slot whose flag is not set.
+fn unflatten_slots(
+    inner: &dyn Space,
+    max_len: usize,
+    flat: &[f32],
+) -> Result<Vec<Sample>, CoreError> {
+    let slot_dim = 1 + inner.flat_dim();
+    check_dim(max_len * slot_dim, flat)?;
+    flat.chunks(slot_dim)
+        .take_while(|slot| slot[0] > 0.5)
+        .map(|slot| inner.unflatten(&slot[1..]))
+        .collect()
+}
+
+/// Ordered collections of between zero and `max_len` values of `inner`, for populations whose size
+/// changes over time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SequenceSpace {
+    pub inner: Box<dyn Space>,
+    pub max_len: usize,
+}
+
+impl PartialEq for SequenceSpace {
+    fn eq(&self, other: &Self) -> bool {
+        self.describe() == other.describe()
+    }
+}
+
+impl Space for SequenceSpace {
+    /// Draws the length uniformly, then each element independently.
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        let len = rng.gen_range(0..=self.max_len);
+        let mut samples = Vec::with_capacity(len);
+        for _ in 0..len {
+            samples.push(self.inner.sample_with_rng(rng)?);
+        }
+        Some(Sample::Sequence(samples))
+    }
+
+    /// Shorter sequences come first, starting with the empty sequence.
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        let products = (0..=self.max_len)
+            .map(|len| Product::new(vec![self.inner.as_ref(); len]))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Box::new(
+            products.into_iter().flatten().map(Sample::Sequence),
+        ))
+    }
+
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        let inner = self.inner.cardinality()?;
+        (0..=self.max_len).try_fold(0usize, |acc, len| {
+            u32::try_from(len)
+                .ok()
+                .and_then(|len| inner.checked_pow(len))
+                .and_then(|count| acc.checked_add(count))
+                .ok_or(CoreError::CardinalityOverflow)
+        })
+    }
+
+    fn contains(&self, sample: &Sample) -> bool {
+        let Sample::Sequence(samples) = sample else {
+            return false;
+        };
+        samples.len() <= self.max_len && samples.iter().all(|v| self.inner.contains(v))
+    }
+
+    fn validate(&self, sample: &Sample) -> Result<(), CoreError> {
+        let Sample::Sequence(samples) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+        if samples.len() > self.max_len {
+            return Err(CoreError::InvalidSample {
+                path: String::new(),
+                reason: format!(
+                    "expected at most {} elements, got {}",
+                    self.max_len,
+                    samples.len()
+                ),
+            });
+        }
+        for (i, v) in samples.iter().enumerate() {
+            self.inner
+                .validate(v)
+                .map_err(|e| prefix_path(e, &format!("[{i}]")))?;
+        }
+        Ok(())
+    }
+
+    fn flat_dim(&self) -> usize {
+        self.max_len * (1 + self.inner.flat_dim())
+    }
+
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        self.validate(sample)?;
+        let Sample::Sequence(samples) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+        flatten_slots(self.inner.as_ref(), self.max_len, samples)
+    }
+
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        unflatten_slots(self.inner.as_ref(), self.max_len, flat).map(Sample::Sequence)
+    }
+
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::Sequence {
+            inner: Box::new(self.inner.describe()),
+            max_len: self.max_len,
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Collections of between zero and `max_len` distinct values of `inner`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetSpace {
+    pub inner: Box<dyn Space>,
+    pub max_len: usize,
+}
+
+impl PartialEq for SetSpace {
+    fn eq(&self, other: &Self) -> bool {
+        self.describe() == other.describe()
+    }
+}
+
+/// Duplicate draws in a row after which a set whose inner space cannot be counted stops growing.
+const MAX_SET_REJECTIONS: usize = 64;
+
+impl Space for SetSpace {
+    /// Draws the size uniformly (capped by the number of values of `inner`), then distinct
+    /// elements by rejection. When `inner` cannot be counted (e.g. a single-point float box), the
+    /// set may come out smaller than drawn rather than wait forever for a new value.
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        let available = self.inner.cardinality().ok();
+        let len = rng.gen_range(0..=self.max_len.min(available.unwrap_or(usize::MAX)));
+        let mut samples: Vec<Sample> = Vec::with_capacity(len);
+        let mut rejections = 0;
+        while samples.len() < len {
+            let sample = self.inner.sample_with_rng(rng)?;
+            if !samples.contains(&sample) {
+                samples.push(sample);
+                rejections = 0;
+            } else if available.is_none() {
+                rejections += 1;
+                if rejections == MAX_SET_REJECTIONS {
+                    break;
                 }
+            }
+        }
+        Some(Sample::Set(samples))
+    }
+
+    /// Smaller sets come first; elements of each set follow the enumeration order of `inner`.
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        let subsets = (0..=self.max_len)
+            .map(|len| {
+                self.inner
+                    .enumerate_iter()
+                    .map(|iter| iter.combinations(len))
             })
-            .collect();
-        if valid_spaces.is_empty() {
-            return None;
+            .collect::<Option<Vec<_>>>()?;
+        Some(Box::new(subsets.into_iter().flatten().map(Sample::Set)))
+    }
+
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        let inner = self.inner.cardinality()? as u128;
+        // Starts from the empty set; C(n, k) = C(n, k - 1) * (n - k + 1) / k.
+        let mut total = 1usize;
+        let mut subsets = 1u128;
+        for len in 1..=(self.max_len as u128).min(inner) {
+            subsets = subsets
+                .checked_mul(inner - len + 1)
+                .ok_or(CoreError::CardinalityOverflow)?
+                / len;
+            total = usize::try_from(subsets)
+                .ok()
+                .and_then(|subsets| total.checked_add(subsets))
+                .ok_or(CoreError::CardinalityOverflow)?;
+        }
+        Ok(total)
+    }
+
+    fn contains(&self, sample: &Sample) -> bool {
+        let Sample::Set(samples) = sample else {
+            return false;
+        };
+        samples.len() <= self.max_len
+            && samples.iter().all(|v| self.inner.contains(v))
+            && samples.iter().all_unique()
+    }
+
+    fn validate(&self, sample: &Sample) -> Result<(), CoreError> {
+        let Sample::Set(samples) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+        if samples.len() > self.max_len {
+            return Err(CoreError::InvalidSample {
+                path: String::new(),
+                reason: format!(
+                    "expected at most {} elements, got {}",
+                    self.max_len,
+                    samples.len()
+                ),
+            });
         }
+        for (i, v) in samples.iter().enumerate() {
+            self.inner
+                .validate(v)
+                .map_err(|e| prefix_path(e, &format!("[{i}]")))?;
+            if samples[..i].contains(v) {
+                return Err(CoreError::InvalidSample {
+                    path: format!("[{i}]"),
+                    reason: format!("duplicate element {v:?}"),
+                });
+            }
+        }
+        Ok(())
+    }
 
-        let mut rng = StdRng::from_entropy();
-        let (index, sub_space) = valid_spaces[rng.gen_range(0..valid_spaces.len())];
-        sub_space
-            .sample()
-            .map(|sample| Sample::OneOf(index as i32, Box::new(sample)))
+    fn flat_dim(&self) -> usize {
+        self.max_len * (1 + self.inner.flat_dim())
     }
 
-    fn sample_with_seed(&self, seed: u64) -> Option<Sample> {
-        let valid_spaces: Vec<_> = self
-            .spaces
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        self.validate(sample)?;
+        let Sample::Set(samples) = sample else {
+            return Err(invalid_sample(self, sample));
+        };
+        flatten_slots(self.inner.as_ref(), self.max_len, samples)
+    }
+
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        unflatten_slots(self.inner.as_ref(), self.max_len, flat).map(Sample::Set)
+    }
+
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::Set {
+            inner: Box::new(self.inner.describe()),
+            max_len: self.max_len,
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Graphs of up to `max_nodes` nodes and `max_edges` directed edges, each carrying a feature
+/// sample, for observations consumed by graph neural networks.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GraphSpace {
+    pub node_space: Box<dyn Space>,
+    pub edge_space: Box<dyn Space>,
+    pub max_nodes: usize,
+    pub max_edges: usize,
+}
+
+impl GraphSpace {
+    fn check_edge_index(
+        &self,
+        nodes: usize,
+        edge_index: &[(usize, usize)],
+    ) -> Result<(), CoreError> {
+        match edge_index
             .iter()
-            .enumerate()
-            .filter(|(_, s)| {
-                if let Some(ds) = s.as_any().downcast_ref::<DiscreteSpace>() {
-                    ds.n > 0
-                } else {
-                    true
-                }
-            })
-            .collect();
-        if valid_spaces.is_empty() {
-            return None;
+            .position(|&(src, dst)| src >= nodes || dst >= nodes)
+        {
+            Some(i) => Err(CoreError::InvalidSample {
+                path: format!(".edge_index[{i}]"),
+                reason: format!("{:?} refers to a missing node", edge_index[i]),
+            }),
+            None => Ok(()),
         }
+    }
+}
 
-        let mut rng = StdRng::seed_from_u64(seed);
-        let (index, sub_space) = valid_spaces[rng.gen_range(0..valid_spaces.len())];
-        sub_space
-            .sample_with_seed(seed + 1)
-            .map(|sample| Sample::OneOf(index as i32, Box::new(sample)))
+impl PartialEq for GraphSpace {
+    fn eq(&self, other: &Self) -> bool {
+        self.describe() == other.describe()
     }
+}
 
-    fn enumerate(&self) -> Option<Vec<Sample>> {
-        let mut all_samples = Vec::new();
-        for (idx, s) in self.spaces.iter().enumerate() {
-            match s.enumerate() {
-                Some(sub_results) => {
-                    for sample in sub_results {
-                        all_samples.push(Sample::OneOf(idx as i32, Box::new(sample)));
-                    }
-                }
-                None => continue,
-            }
+impl Space for GraphSpace {
+    /// Draws the node count uniformly, then (if there are nodes) the edge count, with endpoints
+    /// chosen uniformly among the nodes.
+    fn sample_with_rng(&self, rng: &mut dyn RngCore) -> Option<Sample> {
+        let num_nodes = rng.gen_range(0..=self.max_nodes);
+        let mut nodes = Vec::with_capacity(num_nodes);
+        for _ in 0..num_nodes {
+            nodes.push(self.node_space.sample_with_rng(rng)?);
         }
-        if all_samples.is_empty() {
-            None
+
+        let num_edges = if num_nodes == 0 {
+            0
         } else {
-            Some(all_samples)
+            rng.gen_range(0..=self.max_edges)
+        };
+        let mut edges = Vec::with_capacity(num_edges);
+        let mut edge_index = Vec::with_capacity(num_edges);
+        for _ in 0..num_edges {
+            edge_index.push((rng.gen_range(0..num_nodes), rng.gen_range(0..num_nodes)));
+            edges.push(self.edge_space.sample_with_rng(rng)?);
+        }
+
+        Some(Sample::Graph {
+            nodes,
+            edges,
+            edge_index,
+        })
+    }
+
+    /// Graphs are not enumerated.
+    fn enumerate_iter(&self) -> Option<Box<dyn Iterator<Item = Sample> + '_>> {
+        None
+    }
+
+    fn cardinality(&self) -> Result<usize, CoreError> {
+        Err(CoreError::InvalidOperation(
+            "graph spaces are not enumerated".to_string(),
+        ))
+    }
+
+    fn contains(&self, sample: &Sample) -> bool {
+        self.validate(sample).is_ok()
+    }
+
+    fn validate(&self, sample: &Sample) -> Result<(), CoreError> {
+        let Sample::Graph {
+            nodes,
+            edges,
+            edge_index,
+        } = sample
+        else {
+            return Err(invalid_sample(self, sample));
+        };
+        if nodes.len() > self.max_nodes || edges.len() > self.max_edges {
+            return Err(CoreError::InvalidSample {
+                path: String::new(),
+                reason: format!(
+                    "expected at most {} nodes and {} edges, got {} and {}",
+                    self.max_nodes,
+                    self.max_edges,
+                    nodes.len(),
+                    edges.len()
+                ),
+            });
+        }
+        if edges.len() != edge_index.len() {
+            return Err(CoreError::InvalidSample {
+                path: String::new(),
+                reason: format!(
+                    "{} edge features but {} edge indices",
+                    edges.len(),
+                    edge_index.len()
+                ),
+            });
         }
+        for (i, v) in nodes.iter().enumerate() {
+            self.node_space
+                .validate(v)
+                .map_err(|e| prefix_path(e, &format!(".nodes[{i}]")))?;
+        }
+        for (i, v) in edges.iter().enumerate() {
+            self.edge_space
+                .validate(v)
+                .map_err(|e| prefix_path(e, &format!(".edges[{i}]")))?;
+        }
+        self.check_edge_index(nodes.len(), edge_index)
     }
-    fn len(&self) -> Option<usize> {
-        Some(self.spaces.len())
+
+    /// Node slots as in `SequenceSpace`, followed by edge slots of a presence flag, the source and
+    /// destination indices, and the flattened edge features.
+    fn flat_dim(&self) -> usize {
+        self.max_nodes * (1 + self.node_space.flat_dim())
+            + self.max_edges * (3 + self.edge_space.flat_dim())
+    }
+
+    fn flatten(&self, sample: &Sample) -> Result<Vec<f32>, CoreError> {
+        self.validate(sample)?;
+        let Sample::Graph {
+            nodes,
+            edges,
+            edge_index,
+        } = sample
+        else {
+            return Err(invalid_sample(self, sample));
+        };
+
+        let mut flat = flatten_slots(self.node_space.as_ref(), self.max_nodes, nodes)?;
+        let slot_dim = 3 + self.edge_space.flat_dim();
+        let mut edge_flat = vec![0.0; self.max_edges * slot_dim];
+        for ((slot, edge), &(src, dst)) in edge_flat.chunks_mut(slot_dim).zip(edges).zip(edge_index)
+        {
+            slot[0] = 1.0;
+            slot[1] = src as f32;
+            slot[2] = dst as f32;
+            slot[3..].copy_from_slice(&self.edge_space.flatten(edge)?);
+        }
+        flat.extend(edge_flat);
+        Ok(flat)
+    }
+
+    fn unflatten(&self, flat: &[f32]) -> Result<Sample, CoreError> {
+        check_dim(self.flat_dim(), flat)?;
+        let (node_flat, edge_flat) =
+            flat.split_at(self.max_nodes * (1 + self.node_space.flat_dim()));
+        let nodes = unflatten_slots(self.node_space.as_ref(), self.max_nodes, node_flat)?;
+
+        let mut edges = Vec::new();
+        let mut edge_index = Vec::new();
+        for slot in edge_flat
+            .chunks(3 + self.edge_space.flat_dim())
+            .take_while(|slot| slot[0] > 0.5)
+        {
+            edge_index.push((slot[1].round() as usize, slot[2].round() as usize));
+            edges.push(self.edge_space.unflatten(&slot[3..])?);
+        }
+        self.check_edge_index(nodes.len(), &edge_index)?;
+
+        Ok(Sample::Graph {
+            nodes,
+            edges,
+            edge_index,
+        })
     }
+
     fn is_empty(&self) -> bool {
-        self.spaces.is_empty()
+        false
     }
+
+    fn describe(&self) -> SpaceDescription {
+        SpaceDescription::Graph {
+            node_space: Box::new(self.node_space.describe()),
+            edge_space: Box::new(self.edge_space.describe()),
+            max_nodes: self.max_nodes,
+            max_edges: self.max_edges,
+        }
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Sample {
     // A value sampled from a discrete space.
     Discrete(i32),
@@ -369,13 +1935,80 @@ pub enum Sample {
     // A value sampled from a box space.
     Box(Vec<i32>),
 
+    // A value sampled from a continuous box space.
+    FloatBox(Vec<f32>),
+
+    // A value sampled from a multi-discrete space.
+    MultiDiscrete(Vec<i32>),
+
+    // A value sampled from a multi-binary space.
+    MultiBinary(Vec<bool>),
+
     // A value sampled from a tuple space.
     Tuple(Vec<Sample>),
 
-    // A value sampled from a dictionary space.
-    Dict(HashMap<String, Sample>),
+    // A value sampled from a dictionary space, sorted by key.
+    Dict(BTreeMap<String, Sample>),
+
+    // A value sampled from a sequence space.
+    Sequence(Vec<Sample>),
+
+    // A value sampled from a set space; elements are distinct, and order is ignored by equality
+    // and hashing.
+    Set(Vec<Sample>),
+
+    // A value sampled from a graph space; edge `i` has features `edges[i]` and runs between the
+    // nodes at `edge_index[i]`.
+    Graph {
+        nodes: Vec<Sample>,
+        edges: Vec<Sample>,
+        edge_index: Vec<(usize, usize)>,
+    },
+}
+
+// Float samples compare by bit pattern, matching `Hash`: NaN equals itself, and 0.0 differs
+// from -0.0.
+impl PartialEq for Sample {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Sample::Discrete(a), Sample::Discrete(b)) => a == b,
+            (Sample::OneOf(a_idx, a), Sample::OneOf(b_idx, b)) => a_idx == b_idx && a == b,
+            (Sample::Box(a), Sample::Box(b)) => a == b,
+            (Sample::FloatBox(a), Sample::FloatBox(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+            }
+            (Sample::MultiDiscrete(a), Sample::MultiDiscrete(b)) => a == b,
+            (Sample::MultiBinary(a), Sample::MultiBinary(b)) => a == b,
+            (Sample::Tuple(a), Sample::Tuple(b)) => a == b,
+            (Sample::Dict(a), Sample::Dict(b)) => a == b,
+            (Sample::Sequence(a), Sample::Sequence(b)) => a == b,
+            // Counts each element, so sets with duplicates (which no set space contains) only match
+            // the same multiset.
+            (Sample::Set(a), Sample::Set(b)) => {
+                a.len() == b.len()
+                    && a.iter().all(|v| {
+                        a.iter().filter(|w| *w == v).count() == b.iter().filter(|w| *w == v).count()
+                    })
+            }
+            (
+                Sample::Graph {
+                    nodes: a_nodes,
+                    edges: a_edges,
+                    edge_index: a_edge_index,
+                },
+                Sample::Graph {
+                    nodes: b_nodes,
+                    edges: b_edges,
+                    edge_index: b_edge_index,
+                },
+            ) => a_nodes == b_nodes && a_edges == b_edges && a_edge_index == b_edge_index,
+            _ => false,
+        }
+    }
 }
 
+impl Eq for Sample {}
+
 impl Hash for Sample {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
@@ -387,17 +2020,862 @@ impl Hash for Sample {
             Sample::Box(vec) => {
                 vec.hash(state);
             }
+            Sample::FloatBox(vec) => {
+                for val in vec {
+                    val.to_bits().hash(state);
+                }
+            }
+            Sample::MultiDiscrete(vec) => {
+                vec.hash(state);
+            }
+            Sample::MultiBinary(vec) => {
+                vec.hash(state);
+            }
             Sample::Tuple(vec) => {
                 vec.hash(state);
             }
             Sample::Dict(map) => {
-                let mut sorted_entries: Vec<_> = map.iter().collect();
-                sorted_entries.sort_by(|a, b| a.0.cmp(b.0));
-                for (key, value) in sorted_entries {
-                    key.hash(state);
-                    value.hash(state);
+                map.hash(state);
+            }
+            Sample::Sequence(vec) => {
+                vec.hash(state);
+            }
+            Sample::Set(vec) => {
+                let mut hashes: Vec<u64> = vec
+                    .iter()
+                    .map(|sample| {
+                        let mut hasher = DefaultHasher::new();
+                        sample.hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .collect();
+                hashes.sort_unstable();
+                hashes.hash(state);
+            }
+            Sample::Graph {
+                nodes,
+                edges,
+                edge_index,
+            } => {
+                nodes.hash(state);
+                edges.hash(state);
+                edge_index.hash(state);
+            }
+        }
+    }
+}
+
+/// Many samples of one space in structure-of-arrays form, as produced by `Space::sample_batch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SampleBatch {
+    // One value per sample.
+    Discrete(Vec<i32>),
+
+    // `dim` values per sample, sample-major.
+    Box {
+        values: Vec<i32>,
+        dim: usize,
+    },
+    FloatBox {
+        values: Vec<f32>,
+        dim: usize,
+    },
+    MultiDiscrete {
+        values: Vec<i32>,
+        dim: usize,
+    },
+    MultiBinary {
+        values: Vec<bool>,
+        dim: usize,
+    },
+
+    // One batch per element, each holding `len` samples.
+    Tuple {
+        len: usize,
+        fields: Vec<SampleBatch>,
+    },
+    Dict {
+        len: usize,
+        fields: BTreeMap<String, SampleBatch>,
+    },
+
+    // The branch of each sample, and one batch per sub-space holding the samples of the rows
+    // that picked it, in row order.
+    OneOf {
+        branches: Vec<i32>,
+        batches: Vec<SampleBatch>,
+    },
+
+    // Spaces without a compact form.
+    Samples(Vec<Sample>),
+}
+
+impl PartialEq for SampleBatch {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SampleBatch::Discrete(a), SampleBatch::Discrete(b)) => a == b,
+            (
+                SampleBatch::Box {
+                    values: a,
+                    dim: a_dim,
+                },
+                SampleBatch::Box {
+                    values: b,
+                    dim: b_dim,
+                },
+            )
+            | (
+                SampleBatch::MultiDiscrete {
+                    values: a,
+                    dim: a_dim,
+                },
+                SampleBatch::MultiDiscrete {
+                    values: b,
+                    dim: b_dim,
+                },
+            ) => a_dim == b_dim && a == b,
+            (
+                SampleBatch::FloatBox {
+                    values: a,
+                    dim: a_dim,
+                },
+                SampleBatch::FloatBox {
+                    values: b,
+                    dim: b_dim,
+                },
+            ) => {
+                a_dim == b_dim
+                    && a.len() == b.len()
+                    && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+            }
+            (
+                SampleBatch::MultiBinary {
+                    values: a,
+                    dim: a_dim,
+                },
+                SampleBatch::MultiBinary {
+                    values: b,
+                    dim: b_dim,
+                },
+            ) => a_dim == b_dim && a == b,
+            (
+                SampleBatch::Tuple {
+                    len: a_len,
+                    fields: a,
+                },
+                SampleBatch::Tuple {
+                    len: b_len,
+                    fields: b,
+                },
+            ) => a_len == b_len && a == b,
+            (
+                SampleBatch::Dict {
+                    len: a_len,
+                    fields: a,
+                },
+                SampleBatch::Dict {
+                    len: b_len,
+                    fields: b,
+                },
+            ) => a_len == b_len && a == b,
+            (
+                SampleBatch::OneOf {
+                    branches: a_branches,
+                    batches: a,
+                },
+                SampleBatch::OneOf {
+                    branches: b_branches,
+                    batches: b,
+                },
+            ) => a_branches == b_branches && a == b,
+            (SampleBatch::Samples(a), SampleBatch::Samples(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for SampleBatch {}
+
+impl SampleBatch {
+    /// Number of samples in the batch.
+    pub fn len(&self) -> usize {
+        match self {
+            SampleBatch::Discrete(values) => values.len(),
+            SampleBatch::Box { values, dim } | SampleBatch::MultiDiscrete { values, dim } => {
+                values.len().checked_div(*dim).unwrap_or(0)
+            }
+            SampleBatch::FloatBox { values, dim } => values.len().checked_div(*dim).unwrap_or(0),
+            SampleBatch::MultiBinary { values, dim } => values.len().checked_div(*dim).unwrap_or(0),
+            SampleBatch::Tuple { len, .. } | SampleBatch::Dict { len, .. } => *len,
+            SampleBatch::OneOf { branches, .. } => branches.len(),
+            SampleBatch::Samples(samples) => samples.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits the batch into individual samples, e.g. one per environment for `step`.
+    pub fn into_samples(self) -> Vec<Sample> {
+        match self {
+            SampleBatch::Discrete(values) => values.into_iter().map(Sample::Discrete).collect(),
+            SampleBatch::Box { values, dim } => values
+                .chunks(dim.max(1))
+                .map(|row| Sample::Box(row.to_vec()))
+                .collect(),
+            SampleBatch::FloatBox { values, dim } => values
+                .chunks(dim.max(1))
+                .map(|row| Sample::FloatBox(row.to_vec()))
+                .collect(),
+            SampleBatch::MultiDiscrete { values, dim } => values
+                .chunks(dim.max(1))
+                .map(|row| Sample::MultiDiscrete(row.to_vec()))
+                .collect(),
+            SampleBatch::MultiBinary { values, dim } => values
+                .chunks(dim.max(1))
+                .map(|row| Sample::MultiBinary(row.to_vec()))
+                .collect(),
+            SampleBatch::Tuple { len, fields } => {
+                let mut fields: Vec<_> = fields
+                    .into_iter()
+                    .map(|field| field.into_samples().into_iter())
+                    .collect();
+                (0..len)
+                    .map_while(|_| {
+                        fields
+                            .iter_mut()
+                            .map(Iterator::next)
+                            .collect::<Option<Vec<_>>>()
+                            .map(Sample::Tuple)
+                    })
+                    .collect()
+            }
+            SampleBatch::Dict { len, fields } => {
+                let mut fields: Vec<_> = fields
+                    .into_iter()
+                    .map(|(k, field)| (k, field.into_samples().into_iter()))
+                    .collect();
+                (0..len)
+                    .map_while(|_| {
+                        fields
+                            .iter_mut()
+                            .map(|(k, field)| Some((k.clone(), field.next()?)))
+                            .collect::<Option<BTreeMap<_, _>>>()
+                            .map(Sample::Dict)
+                    })
+                    .collect()
+            }
+            SampleBatch::OneOf { branches, batches } => {
+                let mut batches: Vec<_> = batches
+                    .into_iter()
+                    .map(|batch| batch.into_samples().into_iter())
+                    .collect();
+                branches
+                    .into_iter()
+                    .map_while(|branch| {
+                        let sample = batches.get_mut(usize::try_from(branch).ok()?)?.next()?;
+                        Some(Sample::OneOf(branch, Box::new(sample)))
+                    })
+                    .collect()
+            }
+            SampleBatch::Samples(samples) => samples,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nested() -> OneOfSpace {
+        OneOfSpace {
+            spaces: vec![
+                Box::new(DiscreteSpace { n: 0, start: 0 }),
+                Box::new(TupleSpace {
+                    spaces: vec![
+                        Box::new(DiscreteSpace { n: 2, start: 0 }),
+                        Box::new(BoxSpace {
+                            low: vec![0],
+                            high: vec![3],
+                        }),
+                    ],
+                }),
+                Box::new(DiscreteSpace { n: 4, start: 1 }),
+            ],
+        }
+    }
+
+    macro_rules! flatten_round_trip {
+        ($($name:ident: $space:expr,)*) => {
+            $(
+                paste::paste! {
+                    #[test]
+                    fn [<flatten_round_trip_ $name>]() {
+                        let space = $space;
+                        for seed in 0..32 {
+                            let sample = space.sample_with_seed(seed).unwrap();
+                            let flat = space.flatten(&sample).unwrap();
+                            assert_eq!(flat.len(), space.flat_dim());
+                            assert_eq!(space.unflatten(&flat).unwrap(), sample);
+                        }
+                    }
                 }
+            )*
+        };
+    }
+
+    flatten_round_trip! {
+        discrete: DiscreteSpace { n: 5, start: -2 },
+        box_space: BoxSpace { low: vec![-3, 0, 10], high: vec![3, 0, 20] },
+        float_box: FloatBoxSpace {
+            low: vec![-1.0, 0.0, 0.5, 2.0],
+            high: vec![1.0, 1.0, 0.5, 4.0],
+            shape: Some(vec![2, 2]),
+        },
+        tuple: TupleSpace {
+            spaces: vec![
+                Box::new(DiscreteSpace { n: 3, start: 0 }),
+                Box::new(BoxSpace { low: vec![0, 0], high: vec![5, 5] }),
+            ],
+        },
+        dict: DictSpace {
+            spaces: BTreeMap::from([
+                ("b".to_string(), Box::new(DiscreteSpace { n: 3, start: 0 }) as Box<dyn Space>),
+                ("a".to_string(), Box::new(BoxSpace { low: vec![1], high: vec![9] })),
+                ("c".to_string(), Box::new(nested())),
+            ]),
+        },
+        one_of: nested(),
+        multi_discrete: MultiDiscreteSpace { n: vec![3, 1, 4], start: vec![0, 5, -2] },
+        multi_binary: MultiBinarySpace { n: 6 },
+        sequence: SequenceSpace {
+            inner: Box::new(BoxSpace { low: vec![0, 0], high: vec![9, 3] }),
+            max_len: 4,
+        },
+        set: SetSpace { inner: Box::new(DiscreteSpace { n: 5, start: 0 }), max_len: 3 },
+        graph: GraphSpace {
+            node_space: Box::new(BoxSpace { low: vec![0, 0], high: vec![4, 4] }),
+            edge_space: Box::new(DiscreteSpace { n: 2, start: 0 }),
+            max_nodes: 5,
+            max_edges: 8,
+        },
+    }
+
+    #[test]
+    fn composite_sampling_draws_from_one_rng() {
+        let space = TupleSpace {
+            spaces: vec![
+                Box::new(DiscreteSpace { n: 1000, start: 0 }),
+                Box::new(DiscreteSpace { n: 1000, start: 0 }),
+            ],
+        };
+        let sample = space.sample_with_seed(7).unwrap();
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let expected = Sample::Tuple(vec![
+            Sample::Discrete(rng.gen_range(0..1000)),
+            Sample::Discrete(rng.gen_range(0..1000)),
+        ]);
+        assert_eq!(sample, expected);
+    }
+
+    #[test]
+    fn dict_enumerates_in_key_order() {
+        let space = DictSpace {
+            spaces: BTreeMap::from([
+                (
+                    "b".to_string(),
+                    Box::new(DiscreteSpace { n: 2, start: 0 }) as Box<dyn Space>,
+                ),
+                ("a".to_string(), Box::new(DiscreteSpace { n: 2, start: 0 })),
+            ]),
+        };
+        for sample in space.enumerate().unwrap() {
+            let Sample::Dict(dict) = sample else {
+                unreachable!()
+            };
+            assert!(dict.keys().eq(["a", "b"]));
+        }
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let expected = Sample::Dict(BTreeMap::from([
+            ("a".to_string(), Sample::Discrete(rng.gen_range(0..2))),
+            ("b".to_string(), Sample::Discrete(rng.gen_range(0..2))),
+        ]));
+        assert_eq!(space.sample_with_seed(3).unwrap(), expected);
+    }
+
+    #[test]
+    fn cardinality_matches_enumeration() {
+        let spaces: Vec<Box<dyn Space>> = vec![
+            Box::new(DiscreteSpace { n: 5, start: 1 }),
+            Box::new(BoxSpace {
+                low: vec![0, -1],
+                high: vec![2, 1],
+            }),
+            Box::new(TupleSpace { spaces: vec![] }),
+            Box::new(MultiDiscreteSpace {
+                n: vec![2, 3],
+                start: vec![0, 1],
+            }),
+            Box::new(MultiBinarySpace { n: 3 }),
+            Box::new(SequenceSpace {
+                inner: Box::new(DiscreteSpace { n: 3, start: 0 }),
+                max_len: 3,
+            }),
+            Box::new(SetSpace {
+                inner: Box::new(DiscreteSpace { n: 4, start: 0 }),
+                max_len: 2,
+            }),
+            Box::new(nested()),
+            Box::new(DictSpace {
+                spaces: BTreeMap::from([
+                    ("a".to_string(), Box::new(nested()) as Box<dyn Space>),
+                    ("b".to_string(), Box::new(DiscreteSpace { n: 3, start: 0 })),
+                ]),
+            }),
+            Box::new(OneOfSpace {
+                spaces: vec![
+                    Box::new(DiscreteSpace { n: 3, start: 0 }),
+                    Box::new(FloatBoxSpace {
+                        low: vec![0.0],
+                        high: vec![1.0],
+                        shape: None,
+                    }),
+                ],
+            }),
+        ];
+        for space in spaces {
+            let lazy: Vec<Sample> = space.enumerate_iter().unwrap().collect();
+            assert_eq!(lazy.len(), space.cardinality().unwrap(), "{space:?}");
+            assert_eq!(Some(lazy), space.enumerate());
+        }
+
+        let uncountable = OneOfSpace {
+            spaces: vec![Box::new(FloatBoxSpace {
+                low: vec![0.0],
+                high: vec![1.0],
+                shape: None,
+            })],
+        };
+        assert!(uncountable.enumerate_iter().is_none());
+        assert!(matches!(
+            uncountable.cardinality(),
+            Err(CoreError::Uncountable)
+        ));
+    }
+
+    #[test]
+    fn enumerate_iter_is_lazy_and_cardinality_detects_overflow() {
+        let space = TupleSpace {
+            spaces: (0..8)
+                .map(|_| {
+                    Box::new(BoxSpace {
+                        low: vec![i32::MIN],
+                        high: vec![i32::MAX],
+                    }) as Box<dyn Space>
+                })
+                .collect(),
+        };
+        let first: Vec<Sample> = space.enumerate_iter().unwrap().take(3).collect();
+        assert_eq!(first.len(), 3);
+        assert!(matches!(
+            space.cardinality(),
+            Err(CoreError::CardinalityOverflow)
+        ));
+        assert!(matches!(
+            FloatBoxSpace {
+                low: vec![0.0],
+                high: vec![1.0],
+                shape: None,
+            }
+            .cardinality(),
+            Err(CoreError::Uncountable)
+        ));
+    }
+
+    #[test]
+    fn spaces_and_samples_round_trip_through_json() {
+        let space: Box<dyn Space> = Box::new(DictSpace {
+            spaces: BTreeMap::from([
+                ("action".to_string(), Box::new(nested()) as Box<dyn Space>),
+                (
+                    "features".to_string(),
+                    Box::new(FloatBoxSpace {
+                        low: vec![0.0; 4],
+                        high: vec![1.0; 4],
+                        shape: Some(vec![2, 2]),
+                    }),
+                ),
+            ]),
+        });
+        let json = serde_json::to_string(&space).unwrap();
+        let restored: Box<dyn Space> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.describe(), space.describe());
+
+        let sample = space.sample_with_seed(11).unwrap();
+        let json = serde_json::to_string(&sample).unwrap();
+        assert_eq!(serde_json::from_str::<Sample>(&json).unwrap(), sample);
+    }
+
+    #[test]
+    fn space_descriptions_are_tagged() {
+        let space: Box<dyn Space> = serde_json::from_str(
+            r#"{"type": "OneOf", "spaces": [{"type": "Discrete", "n": 2, "start": 0}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            space.describe(),
+            SpaceDescription::OneOf {
+                spaces: vec![SpaceDescription::Discrete { n: 2, start: 0 }],
+            }
+        );
+    }
+
+    #[test]
+    fn flatten_dict_uses_sorted_keys() {
+        let space = DictSpace {
+            spaces: BTreeMap::from([
+                (
+                    "b".to_string(),
+                    Box::new(BoxSpace {
+                        low: vec![0],
+                        high: vec![9],
+                    }) as Box<dyn Space>,
+                ),
+                (
+                    "a".to_string(),
+                    Box::new(BoxSpace {
+                        low: vec![0],
+                        high: vec![9],
+                    }),
+                ),
+            ]),
+        };
+        let sample = Sample::Dict(BTreeMap::from([
+            ("a".to_string(), Sample::Box(vec![1])),
+            ("b".to_string(), Sample::Box(vec![2])),
+        ]));
+        assert_eq!(space.flatten(&sample).unwrap(), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn flatten_rejects_foreign_samples() {
+        let space = nested();
+        let err = space
+            .flatten(&Sample::OneOf(
+                1,
+                Box::new(Sample::Tuple(vec![Sample::Discrete(0)])),
+            ))
+            .unwrap_err();
+        assert!(matches!(err, CoreError::InvalidSample { path, .. } if path == "<1>"));
+        assert!(matches!(
+            space.unflatten(&[0.0; 3]),
+            Err(CoreError::DimensionMismatch {
+                expected: 7,
+                actual: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn sets_compare_and_hash_without_regard_to_order() {
+        let hash = |sample: &Sample| {
+            let mut hasher = DefaultHasher::new();
+            sample.hash(&mut hasher);
+            hasher.finish()
+        };
+        let set =
+            |values: &[i32]| Sample::Set(values.iter().copied().map(Sample::Discrete).collect());
+        assert_eq!(set(&[0, 1, 2]), set(&[2, 0, 1]));
+        assert_eq!(hash(&set(&[0, 1, 2])), hash(&set(&[2, 0, 1])));
+        assert_ne!(set(&[0, 1]), set(&[0, 2]));
+        assert_ne!(set(&[0, 0, 1]), set(&[0, 1, 1]));
+
+        let sets = SetSpace {
+            inner: Box::new(SetSpace {
+                inner: Box::new(DiscreteSpace { n: 3, start: 0 }),
+                max_len: 2,
+            }),
+            max_len: 2,
+        };
+        let same = Sample::Set(vec![set(&[0, 1]), set(&[1, 0])]);
+        assert!(!sets.contains(&same));
+        assert!(sets.validate(&same).is_err());
+        assert!(
+            sets.enumerate_iter()
+                .unwrap()
+                .any(|s| s == Sample::Set(vec![set(&[2, 1])]))
+        );
+    }
+
+    #[test]
+    fn sets_reject_duplicates_and_sequences_respect_max_len() {
+        let set = SetSpace {
+            inner: Box::new(DiscreteSpace { n: 3, start: 0 }),
+            max_len: 3,
+        };
+        let duplicate = Sample::Set(vec![Sample::Discrete(1), Sample::Discrete(1)]);
+        assert!(!set.contains(&duplicate));
+        assert!(matches!(
+            set.validate(&duplicate),
+            Err(CoreError::InvalidSample { path, .. }) if path == "[1]"
+        ));
+        for seed in 0..32 {
+            assert!(set.contains(&set.sample_with_seed(seed).unwrap()));
+        }
+
+        // Neither inner space can be counted, and each has a single value.
+        let point = SetSpace {
+            inner: Box::new(FloatBoxSpace {
+                low: vec![0.0],
+                high: vec![0.0],
+                shape: None,
+            }),
+            max_len: 2,
+        };
+        let empty_graphs = SetSpace {
+            inner: Box::new(GraphSpace {
+                node_space: Box::new(DiscreteSpace { n: 3, start: 0 }),
+                edge_space: Box::new(DiscreteSpace { n: 1, start: 0 }),
+                max_nodes: 0,
+                max_edges: 0,
+            }),
+            max_len: 3,
+        };
+        for seed in 0..32 {
+            for space in [&point, &empty_graphs] {
+                let sample = space.sample_with_seed(seed).unwrap();
+                assert!(space.contains(&sample));
+                assert!(matches!(sample, Sample::Set(values) if values.len() <= 1));
+            }
+        }
+
+        let sequence = SequenceSpace {
+            inner: Box::new(DiscreteSpace { n: 3, start: 0 }),
+            max_len: 1,
+        };
+        assert!(sequence.contains(&Sample::Sequence(vec![])));
+        assert!(!sequence.contains(&Sample::Sequence(vec![Sample::Discrete(0); 2])));
+    }
+
+    #[test]
+    fn float_samples_compare_like_they_hash() {
+        let hash = |sample: &Sample| {
+            let mut hasher = DefaultHasher::new();
+            sample.hash(&mut hasher);
+            hasher.finish()
+        };
+        let zero = Sample::FloatBox(vec![0.0]);
+        let negative_zero = Sample::FloatBox(vec![-0.0]);
+        let nan = Sample::FloatBox(vec![f32::NAN]);
+        assert_ne!(zero, negative_zero);
+        assert_ne!(hash(&zero), hash(&negative_zero));
+        assert_eq!(nan, nan.clone());
+        assert_eq!(hash(&nan), hash(&nan.clone()));
+
+        let set = SetSpace {
+            inner: Box::new(FloatBoxSpace {
+                low: vec![-1.0],
+                high: vec![1.0],
+                shape: None,
+            }),
+            max_len: 2,
+        };
+        for sample in [
+            Sample::Set(vec![zero.clone(), negative_zero]),
+            Sample::Set(vec![zero.clone(), zero]),
+        ] {
+            assert_eq!(set.contains(&sample), set.validate(&sample).is_ok());
+        }
+    }
+
+    #[test]
+    fn float_batches_compare_like_samples() {
+        let batch = |values: Vec<f32>| SampleBatch::Tuple {
+            len: 1,
+            fields: vec![SampleBatch::FloatBox { values, dim: 2 }],
+        };
+        assert_ne!(batch(vec![0.0, 1.0]), batch(vec![-0.0, 1.0]));
+        assert_eq!(batch(vec![f32::NAN, 1.0]), batch(vec![f32::NAN, 1.0]));
+        assert_ne!(
+            SampleBatch::FloatBox {
+                values: vec![0.0; 4],
+                dim: 2
+            },
+            SampleBatch::FloatBox {
+                values: vec![0.0; 4],
+                dim: 4
             }
+        );
+    }
+
+    #[test]
+    fn one_of_never_picks_empty_branches() {
+        let space = OneOfSpace {
+            spaces: vec![
+                Box::new(DiscreteSpace { n: -1, start: 0 }),
+                Box::new(BoxSpace {
+                    low: vec![],
+                    high: vec![],
+                }),
+                Box::new(TupleSpace { spaces: vec![] }),
+                Box::new(DiscreteSpace { n: 2, start: 0 }),
+            ],
+        };
+        for seed in 0..32 {
+            assert!(matches!(
+                space.sample_with_seed(seed),
+                Some(Sample::OneOf(3, _))
+            ));
+            assert!(matches!(
+                space.sample_branch_weighted_with_seed(&[1.0; 4], seed),
+                Some(Sample::OneOf(3, _))
+            ));
         }
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(space.sample_batch(8, &mut rng).is_some());
+        assert!(DiscreteSpace { n: -1, start: 0 }.is_empty());
+    }
+
+    #[test]
+    fn graph_rejects_dangling_edges() {
+        let space = GraphSpace {
+            node_space: Box::new(DiscreteSpace { n: 3, start: 0 }),
+            edge_space: Box::new(BoxSpace {
+                low: vec![0],
+                high: vec![1],
+            }),
+            max_nodes: 2,
+            max_edges: 2,
+        };
+        let graph = Sample::Graph {
+            nodes: vec![Sample::Discrete(0), Sample::Discrete(2)],
+            edges: vec![Sample::Box(vec![1]), Sample::Box(vec![0])],
+            edge_index: vec![(0, 1), (1, 2)],
+        };
+        assert!(!space.contains(&graph));
+        assert!(matches!(
+            space.validate(&graph),
+            Err(CoreError::InvalidSample { path, .. }) if path == ".edge_index[1]"
+        ));
+    }
+
+    #[test]
+    fn weighted_sampling_follows_weights() {
+        let space = DiscreteSpace { n: 4, start: 1 };
+        for seed in 0..16 {
+            assert_eq!(
+                space.sample_weighted_with_seed(&[0.0, 0.0, 2.5, 0.0], seed),
+                Some(Sample::Discrete(3))
+            );
+        }
+        assert_eq!(space.sample_weighted_with_seed(&[1.0; 3], 0), None);
+        assert_eq!(space.sample_weighted_with_seed(&[0.0; 4], 0), None);
+
+        let space = nested();
+        for seed in 0..16 {
+            let Some(Sample::OneOf(branch, _)) =
+                space.sample_branch_weighted_with_seed(&[5.0, 0.0, 1.0], seed)
+            else {
+                panic!("expected a OneOf sample");
+            };
+            assert_eq!(branch, 2);
+        }
+        assert_eq!(
+            space.sample_branch_weighted_with_seed(&[1.0, 0.0, 0.0], 0),
+            None
+        );
+    }
+
+    #[test]
+    fn sample_batch_matches_space() {
+        let spaces: Vec<Box<dyn Space>> = vec![
+            Box::new(DiscreteSpace { n: 5, start: -2 }),
+            Box::new(BoxSpace {
+                low: vec![0, -4],
+                high: vec![3, 4],
+            }),
+            Box::new(MultiBinarySpace { n: 3 }),
+            Box::new(SequenceSpace {
+                inner: Box::new(DiscreteSpace { n: 2, start: 0 }),
+                max_len: 2,
+            }),
+            Box::new(DictSpace {
+                spaces: BTreeMap::from([
+                    ("a".to_string(), Box::new(nested()) as Box<dyn Space>),
+                    (
+                        "b".to_string(),
+                        Box::new(FloatBoxSpace {
+                            low: vec![0.0; 2],
+                            high: vec![1.0; 2],
+                            shape: None,
+                        }),
+                    ),
+                ]),
+            }),
+        ];
+        for space in spaces {
+            let batch = space
+                .sample_batch(64, &mut StdRng::seed_from_u64(5))
+                .unwrap();
+            assert_eq!(batch.len(), 64);
+            let samples = batch.into_samples();
+            assert_eq!(samples.len(), 64);
+            assert!(samples.iter().all(|s| space.contains(s)), "{space:?}");
+        }
+
+        // Leaf spaces draw in the same order as repeated sampling.
+        let space = BoxSpace {
+            low: vec![0, 0],
+            high: vec![100, 100],
+        };
+        let mut rng = StdRng::seed_from_u64(9);
+        let expected: Vec<Sample> = (0..8)
+            .map(|_| space.sample_with_rng(&mut rng).unwrap())
+            .collect();
+        let batch = space
+            .sample_batch(8, &mut StdRng::seed_from_u64(9))
+            .unwrap();
+        assert_eq!(batch.into_samples(), expected);
+    }
+
+    #[test]
+    fn spaces_compare_and_display_structurally() {
+        let a: Box<dyn Space> = Box::new(nested());
+        let b: Box<dyn Space> = Box::new(nested());
+        assert!(*a == *b);
+        assert_eq!(a.kind(), SpaceKind::OneOf);
+        let c: Box<dyn Space> = Box::new(DiscreteSpace { n: 4, start: 1 });
+        assert!(*a != *c);
+        assert_eq!(
+            TupleSpace { spaces: vec![a] },
+            TupleSpace { spaces: vec![b] }
+        );
+        assert_eq!(
+            nested().describe().to_string(),
+            "OneOf(Discrete(0), Tuple(Discrete(2), Box([0], [3])), Discrete(4, start=1))"
+        );
+
+        let dict: Box<dyn Space> = Box::new(DictSpace {
+            spaces: BTreeMap::from([
+                (
+                    "mask".to_string(),
+                    Box::new(MultiBinarySpace { n: 3 }) as Box<dyn Space>,
+                ),
+                (
+                    "fires".to_string(),
+                    Box::new(SequenceSpace {
+                        inner: Box::new(DiscreteSpace { n: 5, start: 0 }),
+                        max_len: 2,
+                    }),
+                ),
+            ]),
+        });
+        assert_eq!(
+            dict.to_string(),
+            r#"Dict("fires": Sequence(Discrete(5), max_len=2), "mask": MultiBinary(3))"#
+        );
     }
 }
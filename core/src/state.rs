@@ -10,6 +10,19 @@ pub trait IndexView<'a> {
     }
 }
 
+/// Mutable counterpart of `IndexView`, for updating one index (e.g. one environment) at a time.
+pub trait IndexViewMut {
+    type ViewMut<'s>
+    where
+        Self: 's;
+
+    fn index_view_mut(&mut self, idx: usize) -> Self::ViewMut<'_>;
+
+    /// One view per index, disjoint from each other so they can be updated independently (and
+    /// in parallel).
+    fn split_envs_mut(&mut self) -> Vec<Self::ViewMut<'_>>;
+}
+
 pub trait State<'a>: IndexView<'a> {
     type Config;
 
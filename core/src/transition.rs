@@ -1,17 +1,21 @@
 use color_eyre::Result;
+use rand::RngCore;
 use std::any::Any;
 use std::collections::HashMap;
 
-use crate::spaces::Sample;
-use crate::state::State;
+pub type EnvironmentOutput = HashMap<String, Box<dyn Any + Send>>;
 
-type EnvironmentOutput = HashMap<String, Box<dyn Any>>;
-
-pub trait Transition<S: for<'a> State<'a>> {
+/// One stage of an environment's step, applied to `S` (typically the state of a single
+/// environment).
+///
+/// Transitions receive the outputs of the stages before them. The environment validates and
+/// decodes actions before the first stage and passes them in `outputs` (for the wildfire
+/// environment, under `wildfire::transitions::ACTIONS`).
+pub trait Transition<S> {
     fn apply(
         &self,
         state: &mut S,
-        actions: &HashMap<String, Vec<Sample>>,
         outputs: &EnvironmentOutput,
+        rng: &mut dyn RngCore,
     ) -> Result<EnvironmentOutput>;
 }
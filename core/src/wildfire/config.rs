@@ -29,6 +29,19 @@ pub struct WildfireConfiguration {
     pub initial_fires: Vec<(usize, u8, u8, u16, u8)>,
 
     pub initial_fuel: Vec<u8>,
+
+    /// number of steps after which an episode is truncated (never, if unset)
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+    /// manhattan distance within which agents are linked in graph observations (never, if unset)
+    #[serde(default)]
+    pub comm_range: Option<u8>,
+    /// step environments one after another instead of in parallel (results are identical)
+    #[serde(default)]
+    pub sequential: bool,
+    /// reset environments whose episode ended at the end of `step_batch`
+    #[serde(default)]
+    pub auto_reset: bool,
 }
 
 impl Configuration for WildfireConfiguration {
@@ -150,6 +163,51 @@ impl Configuration for WildfireConfiguration {
     }
 }
 
+/// Test fixtures: start from `WildfireConfiguration::test` and adjust it with the `with_*` methods
+/// or struct update syntax.
+#[cfg(test)]
+impl WildfireConfiguration {
+    /// `num_envs` environments on `grid` with no agents or fires, room for 4 agents and 4 fires
+    /// in every cell, and 100 fuel everywhere.
+    pub(crate) fn test(num_envs: usize, grid: (u8, u8)) -> Self {
+        let num_spaces = grid.0 as usize * grid.1 as usize;
+        WildfireConfiguration {
+            num_envs,
+            grid,
+            max_agents: 4,
+            max_fires: 4,
+            max_agents_per_space: vec![4; num_spaces],
+            max_fires_per_space: vec![4; num_spaces],
+            initial_agents: vec![],
+            initial_fires: vec![],
+            initial_fuel: vec![100; num_spaces],
+            max_steps: None,
+            comm_range: None,
+            sequential: false,
+            auto_reset: false,
+        }
+    }
+
+    /// Allows `max_agents` agents in total and `per_space` in every cell.
+    pub(crate) fn with_max_agents(mut self, max_agents: usize, per_space: usize) -> Self {
+        self.max_agents = max_agents;
+        self.max_agents_per_space.fill(per_space);
+        self
+    }
+
+    /// Allows `max_fires` fires in total and `per_space` in every cell.
+    pub(crate) fn with_max_fires(mut self, max_fires: usize, per_space: usize) -> Self {
+        self.max_fires = max_fires;
+        self.max_fires_per_space.fill(per_space);
+        self
+    }
+
+    pub(crate) fn with_fuel(mut self, fuel: u8) -> Self {
+        self.initial_fuel.fill(fuel);
+        self
+    }
+}
+
 #[cfg(feature = "arbitrary")]
 impl<'a> Arbitrary<'a> for WildfireConfiguration {
     fn arbitrary(u: &mut Unstructured<'a>) -> ArbitraryResult<Self> {
@@ -206,6 +264,11 @@ impl<'a> Arbitrary<'a> for WildfireConfiguration {
             .map(|((y, x, size, intensity), count)| (count, y, x, size, intensity))
             .collect();
 
+        let max_steps = u.arbitrary()?;
+        let comm_range = u.arbitrary()?;
+        let sequential = u.arbitrary()?;
+        let auto_reset = u.arbitrary()?;
+
         let grid_len = grid.0 as usize * grid.1 as usize;
         let total_fires = initial_fires.iter().map(|(count, ..)| count).sum::<usize>();
         let mut initial_fuel: Vec<u8> = {
@@ -234,6 +297,10 @@ impl<'a> Arbitrary<'a> for WildfireConfiguration {
             initial_agents,
             initial_fires,
             initial_fuel,
+            max_steps,
+            comm_range,
+            sequential,
+            auto_reset,
         })
     }
 }
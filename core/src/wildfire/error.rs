@@ -10,6 +10,19 @@ pub enum WildfireError {
     AgentCapacityExceeded { attempted: usize, max: usize },
     #[error("Fire capacity exceeded: attempted {attempted}, max {max}")]
     FireCapacityExceeded { attempted: usize, max: usize },
+    #[error("Environment index out of bounds: {0}")]
+    EnvIndexOutOfBounds(usize),
+    #[error("Unknown agent: {0}")]
+    UnknownAgent(String),
+    #[error("Invalid action for agent {agent}: {source}")]
+    InvalidAction {
+        agent: String,
+        source: crate::error::CoreError,
+    },
+    #[error("Malformed action for agent {agent}: {reason}")]
+    MalformedAction { agent: String, reason: String },
+    #[error("Incompatible snapshot: {0}")]
+    IncompatibleSnapshot(String),
     #[error("Invalid wildfire operation: {0}")]
     InvalidWildfireOperation(String),
     #[error(transparent)]
@@ -1,38 +1,54 @@
 pub mod config;
 pub mod error;
+pub mod seeding;
+pub mod snapshot;
+pub mod spaces;
 pub mod state;
 pub mod transitions;
 
 use async_std::task;
 use bumpalo::Bump;
 use color_eyre::Result;
-use rand::SeedableRng;
-use rand::rngs::StdRng;
+use color_eyre::eyre::eyre;
 use sqlx::SqlitePool;
-use std::any::Any;
 use std::collections::HashMap;
-use std::iter::{repeat_n, repeat_with};
+use std::iter::repeat_n;
 use uuid::Uuid;
 
 use crate::config::Configuration;
-use crate::env::{LoggableEnvironment, SimulatedEnvironment};
-use crate::spaces::{Sample, Space};
+use crate::env::{EnvStepResult, LoggableEnvironment, SimulatedEnvironment, StepResult};
+use crate::spaces::{DictSpace, GraphSpace, OneOfSpace, Sample, SampleBatch, Space};
 use crate::state::State;
+use crate::transition::EnvironmentOutput;
 use crate::wildfire::config::WildfireConfiguration;
+use crate::wildfire::error::WildfireError;
+use crate::wildfire::seeding::WildfireRng;
+use crate::wildfire::snapshot::WildfireSnapshot;
+use crate::wildfire::spaces::WildfireAction;
 use crate::wildfire::state::WildfireState;
 use crate::wildfire::transitions::WildfireTransition;
 
 #[allow(dead_code)]
 pub struct WildfireEnvironment<'a> {
     arena: &'a Bump,
-    rng: StdRng,
+    /// Master seed, see `seeding`
+    seed: u64,
+    /// Draws agent names
+    rng: WildfireRng,
+    /// One generator per transition of each environment
+    env_rngs: Vec<Vec<WildfireRng>>,
+    /// Agents placed in every environment, chosen on `reset`
+    agents: Vec<(Uuid, u8, u8, u8, u8, u8, u8)>,
 
     config: WildfireConfiguration,
 
     state: WildfireState<'a>,
-    transitions: Vec<Box<WildfireTransition<'a>>>,
+    transitions: Vec<Box<WildfireTransition>>,
 
-    outputs: HashMap<String, Box<dyn Any>>,
+    outputs: Vec<EnvironmentOutput>,
+    action_space: OneOfSpace,
+    observation_space: DictSpace,
+    graph_observation_space: GraphSpace,
     db: sqlx::SqlitePool,
 }
 
@@ -55,42 +71,218 @@ impl<'a> SimulatedEnvironment<'a> for WildfireEnvironment<'a> {
             Ok::<sqlx::SqlitePool, color_eyre::Report>(pool)
         })?;
 
+        Ok(Self::with_db(config, arena, db))
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.state.clear();
+        let mut agents = Vec::new();
+        for &(count, y, x, power, suppressant, capacity, equipment) in &self.config.initial_agents {
+            for _ in 0..count {
+                let name = seeding::agent_id(&mut self.rng);
+                agents.push((name, y, x, power, suppressant, capacity, equipment));
+            }
+        }
+        self.agents = agents;
+        for env_idx in 0..self.config.num_envs {
+            self.populate_env(env_idx)?;
+        }
+
+        Ok(())
+    }
+
+    fn reset_seeded(&mut self, seed: u64) -> Result<()> {
+        self.reseed(seed);
+
+        self.reset()
+    }
+
+    fn reset_env(&mut self, env_idx: usize, seed: Option<u64>) -> Result<()> {
+        if env_idx >= self.state.num_envs {
+            return Err(WildfireError::EnvIndexOutOfBounds(env_idx).into());
+        }
+        if let Some(seed) = seed {
+            self.env_rngs[env_idx] =
+                seeding::transition_rngs(seeding::env_seed(seed, env_idx), self.transitions.len());
+        }
+
+        self.state.clear_env(env_idx);
+        self.populate_env(env_idx)
+    }
+
+    fn step(&mut self, actions: &HashMap<String, Vec<Sample>>) -> Result<StepResult> {
+        let decoded = (0..self.state.num_envs)
+            .map(|env_idx| {
+                self.decode_actions(
+                    env_idx,
+                    actions
+                        .iter()
+                        .filter_map(|(agent, samples)| Some((agent, samples.get(env_idx)?))),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.advance(decoded)?;
+
+        let num_envs = self.state.num_envs;
+        let mut result = StepResult::default();
+        for env_idx in 0..num_envs {
+            let outcome = self.env_outcome(env_idx)?;
+            for (name, reward) in outcome.rewards {
+                result
+                    .rewards
+                    .entry(name)
+                    .or_insert_with(|| vec![0.0; num_envs])[env_idx] = reward;
+            }
+            for (name, terminated) in outcome.terminated {
+                result
+                    .terminated
+                    .entry(name)
+                    .or_insert_with(|| vec![false; num_envs])[env_idx] = terminated;
+            }
+            for (name, truncated) in outcome.truncated {
+                result
+                    .truncated
+                    .entry(name)
+                    .or_insert_with(|| vec![false; num_envs])[env_idx] = truncated;
+            }
+            for (name, info) in outcome.infos {
+                result
+                    .infos
+                    .entry(name)
+                    .or_insert_with(|| vec![HashMap::new(); num_envs])[env_idx] = info;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn step_batch(&mut self, actions: HashMap<String, SampleBatch>) -> Result<Vec<EnvStepResult>> {
+        let mut samples = HashMap::with_capacity(actions.len());
+        for (agent, batch) in actions {
+            let batch = batch.into_samples();
+            if batch.len() != self.state.num_envs {
+                return Err(eyre!(
+                    "expected actions of {agent} for {} environments, got {}",
+                    self.state.num_envs,
+                    batch.len()
+                ));
+            }
+            samples.insert(agent, batch);
+        }
+
+        let decoded = (0..self.state.num_envs)
+            .map(|env_idx| {
+                self.decode_actions(
+                    env_idx,
+                    samples
+                        .iter()
+                        .map(|(agent, samples)| (agent, &samples[env_idx])),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.advance(decoded)?;
+
+        let mut results = Vec::with_capacity(self.state.num_envs);
+        for env_idx in 0..self.state.num_envs {
+            let mut outcome = self.env_outcome(env_idx)?;
+            outcome.observations = self.observe_env(env_idx);
+            if self.config.auto_reset && self.episode_over(env_idx) {
+                self.reset_env(env_idx, None)?;
+                outcome.final_observations = Some(std::mem::replace(
+                    &mut outcome.observations,
+                    self.observe_env(env_idx),
+                ));
+            }
+            results.push(outcome);
+        }
+        Ok(results)
+    }
+
+    fn state(&self) -> &WildfireState<'a> {
+        &self.state
+    }
+
+    fn observe(&self, agent: &str) -> Result<Vec<Sample>> {
+        (0..self.state.num_envs)
+            .map(|env_idx| {
+                let agent_idx = self.agent_index(env_idx, agent)?;
+                Ok(spaces::observe(
+                    &self.state,
+                    &self.config,
+                    env_idx,
+                    agent_idx,
+                ))
+            })
+            .collect()
+    }
+
+    fn action_space(&self, _agent: &str) -> &dyn Space {
+        &self.action_space
+    }
+
+    fn action_mask(&self, agent: &str) -> Result<Vec<Vec<bool>>> {
+        (0..self.state.num_envs)
+            .map(|env_idx| {
+                let agent_idx = self.agent_index(env_idx, agent)?;
+                Ok(spaces::action_mask(
+                    &self.action_space,
+                    &self.state,
+                    &self.config,
+                    env_idx,
+                    agent_idx,
+                ))
+            })
+            .collect()
+    }
+
+    fn observation_space(&self, _agent: &str) -> &dyn Space {
+        &self.observation_space
+    }
+}
+
+impl<'a> WildfireEnvironment<'a> {
+    /// Builds the environment around an open database, seeded from entropy.
+    fn with_db(config: WildfireConfiguration, arena: &'a Bump, db: sqlx::SqlitePool) -> Self {
         let state = WildfireState::new(&config, arena);
-        Ok(WildfireEnvironment {
+        let transitions = transitions::default_transitions(&config);
+        let action_space = spaces::action_space(&config);
+        let observation_space = spaces::observation_space(&config);
+        let graph_observation_space = spaces::graph_observation_space(&config);
+        let mut env = WildfireEnvironment {
             arena,
-            rng: StdRng::from_entropy(),
+            seed: 0,
+            rng: seeding::agent_rng(0),
+            env_rngs: Vec::new(),
+            agents: Vec::new(),
             config,
             state,
-            transitions: vec![],
-            outputs: HashMap::new(),
+            transitions,
+            outputs: Vec::new(),
+            action_space,
+            observation_space,
+            graph_observation_space,
             db,
-        })
+        };
+        env.reseed(rand::random());
+        env
     }
+}
 
-    fn reset(&mut self) -> Result<()> {
-        self.state.clear();
-        let agents: Vec<(Uuid, u8, u8, u8, u8, u8, u8)> = self
-            .config
-            .initial_agents
-            .iter()
-            .flat_map(|(count, y, x, power, suppressant, capacity, equipment)| {
-                repeat_with(|| {
-                    (
-                        Uuid::new_v4(),
-                        *y,
-                        *x,
-                        *power,
-                        *suppressant,
-                        *capacity,
-                        *equipment,
-                    )
-                })
-                .take(*count)
+impl WildfireEnvironment<'_> {
+    /// Restarts every random stream from the master seed `seed`.
+    fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = seeding::agent_rng(seed);
+        self.env_rngs = (0..self.config.num_envs)
+            .map(|env_idx| {
+                seeding::transition_rngs(seeding::env_seed(seed, env_idx), self.transitions.len())
             })
             .collect();
-        for env_idx in 0..self.config.num_envs {
-            self.state.agent.add_agents(env_idx, &agents)?;
-        }
+    }
+
+    /// Places the configured agents, fires and fuel in environment `env_idx`, which must be empty.
+    fn populate_env(&mut self, env_idx: usize) -> Result<()> {
+        self.state.agent.add_agents(env_idx, &self.agents)?;
 
         let fires: Vec<(u8, u8, u16, u8)> = self
             .config
@@ -100,45 +292,494 @@ impl<'a> SimulatedEnvironment<'a> for WildfireEnvironment<'a> {
                 repeat_n((*y, *x, *size, *intensity), *count)
             })
             .collect();
-        for env_idx in 0..self.config.num_envs {
-            self.state.env.add_fires(env_idx, &fires)?;
-        }
+        self.state.env.add_fires(env_idx, &fires)?;
 
         let grid_len = self.config.grid.0 as usize * self.config.grid.1 as usize;
-        for env_idx in 0..self.config.num_envs {
-            let start = env_idx * grid_len;
-            let end = start + grid_len;
-            self.state.env.fuel[start..end].copy_from_slice(&self.config.initial_fuel);
+        let start = env_idx * grid_len;
+        self.state.env.fuel[start..start + grid_len].copy_from_slice(&self.config.initial_fuel);
+        Ok(())
+    }
+
+    /// Every fire in environment `env_idx` is out.
+    fn terminated(&self, env_idx: usize) -> bool {
+        let (start, end) = self.state.env.offsets[env_idx];
+        start == end
+    }
+
+    /// Environment `env_idx` has reached `max_steps`.
+    fn truncated(&self, env_idx: usize) -> bool {
+        self.config
+            .max_steps
+            .is_some_and(|max_steps| self.state.env.timestep[env_idx] as usize >= max_steps)
+    }
+
+    fn episode_over(&self, env_idx: usize) -> bool {
+        self.terminated(env_idx) || self.truncated(env_idx)
+    }
+
+    /// Observation of every agent in environment `env_idx`, keyed by name.
+    fn observe_env(&self, env_idx: usize) -> HashMap<String, Sample> {
+        let (start, end) = self.state.agent.offsets[env_idx];
+        (start..end)
+            .map(|agent_idx| {
+                (
+                    self.state.agent.name[agent_idx].to_string(),
+                    spaces::observe(&self.state, &self.config, env_idx, agent_idx),
+                )
+            })
+            .collect()
+    }
+
+    /// Validates and decodes the actions of environment `env_idx`, given as `(agent, sample)`
+    /// pairs. Agents without an action idle; actions for agents not in the environment are
+    /// rejected.
+    fn decode_actions<'s>(
+        &self,
+        env_idx: usize,
+        actions: impl IntoIterator<Item = (&'s String, &'s Sample)>,
+    ) -> Result<Vec<WildfireAction>, WildfireError> {
+        let max_local_fires = spaces::max_local_fires(&self.config);
+        let (start, end) = self.state.agent.offsets[env_idx];
+        let mut decoded = vec![WildfireAction::Idle; end - start];
+        for (agent, sample) in actions {
+            let agent_idx = self.agent_index(env_idx, agent)?;
+            decoded[agent_idx - start] =
+                transitions::decode_action(&self.action_space, max_local_fires, agent, sample)?;
         }
+        Ok(decoded)
+    }
 
+    /// Runs the transition pipeline once over every environment with its decoded `actions`. Every
+    /// action must be decoded first, so a rejected one leaves the state untouched.
+    fn advance(&mut self, actions: Vec<Vec<WildfireAction>>) -> Result<()> {
+        self.outputs = transitions::run(
+            &self.transitions,
+            &mut self.state,
+            actions,
+            &mut self.env_rngs,
+            self.config.sequential,
+        )?;
+
+        for timestep in self.state.env.timestep.iter_mut() {
+            *timestep += 1;
+        }
         Ok(())
     }
 
-    fn reset_seeded(&mut self, seed: u64) -> Result<()> {
-        self.rng = StdRng::seed_from_u64(seed);
+    /// Rewards, termination and infos of every agent in environment `env_idx` after `advance`.
+    fn env_outcome(&self, env_idx: usize) -> Result<EnvStepResult> {
+        let outputs = &self.outputs[env_idx];
+        let suppressed = outputs
+            .get(transitions::SUPPRESSED)
+            .and_then(|output| output.downcast_ref::<Vec<u16>>())
+            .ok_or_else(|| eyre!("suppression transition produced no output"))?;
+        let burned_out = *outputs
+            .get(transitions::BURNED_OUT)
+            .and_then(|output| output.downcast_ref::<usize>())
+            .ok_or_else(|| eyre!("burnout transition produced no output"))?;
 
-        self.reset()
+        let (fire_start, fire_end) = self.state.env.offsets[env_idx];
+        let fires = fire_end - fire_start;
+        let terminated = self.terminated(env_idx);
+        let truncated = self.truncated(env_idx);
+
+        let mut outcome = EnvStepResult::default();
+        let (agent_start, agent_end) = self.state.agent.offsets[env_idx];
+        let names = &self.state.agent.name[agent_start..agent_end];
+        for (name, &suppressed) in names.iter().zip(suppressed) {
+            let name = name.to_string();
+            let reward = suppressed as f32;
+            let info = HashMap::from([
+                ("suppressed".to_string(), reward),
+                ("fires".to_string(), fires as f32),
+                ("burned_out".to_string(), burned_out as f32),
+            ]);
+
+            outcome.rewards.insert(name.clone(), reward);
+            outcome.terminated.insert(name.clone(), terminated);
+            outcome.truncated.insert(name.clone(), truncated);
+            outcome.infos.insert(name, info);
+        }
+        Ok(outcome)
     }
 
-    fn step(&mut self, _actions: &HashMap<String, Vec<Sample>>) -> Result<()> {
-        todo!()
+    /// Space of the samples returned by `observe_graph`.
+    pub fn graph_observation_space(&self) -> &dyn Space {
+        &self.graph_observation_space
     }
 
-    fn state(&self) -> &WildfireState<'a> {
-        &self.state
+    /// Encodes each environment as a graph of its agents and fires, one sample per environment.
+    pub fn observe_graph(&self) -> Vec<Sample> {
+        (0..self.state.num_envs)
+            .map(|env_idx| spaces::observe_graph(&self.state, &self.config, env_idx))
+            .collect()
     }
 
-    fn observe(&self, _agent: &str) -> Result<()> {
-        todo!()
+    /// Owned copy of the state and every random stream, from which `restore` resumes exactly.
+    pub fn snapshot(&self) -> WildfireSnapshot {
+        WildfireSnapshot {
+            seed: self.seed,
+            rng: self.rng.clone(),
+            env_rngs: self.env_rngs.clone(),
+            agents: self.agents.clone(),
+            state: self.state.snapshot(),
+        }
     }
 
-    fn action_space(&self, _agent: &str) -> &dyn Space {
-        todo!()
+    /// Rewinds to `snapshot`, which must come from an environment with the same configuration. On
+    /// failure the environment is left untouched.
+    pub fn restore(&mut self, snapshot: &WildfireSnapshot) -> Result<()> {
+        if snapshot.env_rngs.len() != self.state.num_envs
+            || snapshot
+                .env_rngs
+                .iter()
+                .any(|rngs| rngs.len() != self.transitions.len())
+        {
+            return Err(WildfireError::IncompatibleSnapshot(format!(
+                "expected {} generators for each of {} environments",
+                self.transitions.len(),
+                self.state.num_envs
+            ))
+            .into());
+        }
+        self.state.restore(&snapshot.state)?;
+
+        self.seed = snapshot.seed;
+        self.rng = snapshot.rng.clone();
+        self.env_rngs = snapshot.env_rngs.clone();
+        self.agents = snapshot.agents.clone();
+        self.outputs.clear();
+        Ok(())
     }
 
-    fn observation_space(&self, _agent: &str) -> &dyn Space {
-        todo!()
+    /// Deep copy in `arena` that, until either is stepped, behaves exactly like this environment
+    /// but shares nothing mutable with it. Take `arena` from an `ArenaPool` to reuse its memory
+    /// across forks.
+    pub fn fork<'b>(&self, arena: &'b Bump) -> WildfireEnvironment<'b> {
+        WildfireEnvironment {
+            arena,
+            seed: self.seed,
+            rng: self.rng.clone(),
+            env_rngs: self.env_rngs.clone(),
+            agents: self.agents.clone(),
+            config: self.config.clone(),
+            state: self.state.fork(arena),
+            transitions: transitions::default_transitions(&self.config),
+            outputs: Vec::new(),
+            action_space: spaces::action_space(&self.config),
+            observation_space: spaces::observation_space(&self.config),
+            graph_observation_space: spaces::graph_observation_space(&self.config),
+            db: self.db.clone(),
+        }
+    }
+
+    /// Finds the absolute slot of `agent` within environment `env_idx`.
+    fn agent_index(&self, env_idx: usize, agent: &str) -> Result<usize, WildfireError> {
+        let name =
+            Uuid::parse_str(agent).map_err(|_| WildfireError::UnknownAgent(agent.to_string()))?;
+        let (start, end) = self.state.agent.offsets[env_idx];
+        (start..end)
+            .find(|&i| self.state.agent.name[i] == name)
+            .ok_or_else(|| WildfireError::UnknownAgent(agent.to_string()))
     }
 }
 
 impl LoggableEnvironment for WildfireEnvironment<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    /// Environment reset with `seed`, without the on-disk database.
+    fn env(config: WildfireConfiguration, arena: &Bump, seed: u64) -> WildfireEnvironment<'_> {
+        let db = SqlitePool::connect_lazy("sqlite::memory:").unwrap();
+        let mut env = WildfireEnvironment::with_db(config, arena, db);
+        env.reset_seeded(seed).unwrap();
+        env
+    }
+
+    fn config() -> WildfireConfiguration {
+        WildfireConfiguration {
+            initial_agents: vec![(2, 1, 1, 3, 2, 4, 1)],
+            initial_fires: vec![(1, 1, 1, 30, 100), (1, 2, 3, 10, 60)],
+            ..WildfireConfiguration::test(3, (4, 4)).with_fuel(120)
+        }
+    }
+
+    /// A random action for every agent in every environment.
+    fn random_actions(env: &WildfireEnvironment, rng: &mut StdRng) -> HashMap<String, Vec<Sample>> {
+        env.agents
+            .iter()
+            .map(|(name, ..)| {
+                let samples = (0..env.state.num_envs)
+                    .map(|_| env.action_space.sample_with_rng(rng).unwrap())
+                    .collect();
+                (name.to_string(), samples)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rejected_step_leaves_state_unchanged() {
+        let arena = Bump::new();
+        let mut env = env(config(), &arena, 9);
+        let before = env.snapshot();
+
+        let idle = Sample::OneOf(spaces::IDLE, Box::new(Sample::Discrete(0)));
+        let refill = Sample::OneOf(spaces::REFILL, Box::new(Sample::Discrete(0)));
+        let mut actions: HashMap<String, Vec<Sample>> = env
+            .agents
+            .iter()
+            .map(|(name, ..)| {
+                (
+                    name.to_string(),
+                    vec![idle.clone(), refill.clone(), idle.clone()],
+                )
+            })
+            .collect();
+        let invalid = Sample::OneOf(spaces::MOVE, Box::new(Sample::Discrete(7)));
+        actions.values_mut().next().unwrap()[2] = invalid;
+
+        assert!(env.step(&actions).is_err());
+        assert_eq!(env.snapshot(), before);
+    }
+
+    #[test]
+    fn step_batch_reports_each_environment() {
+        let arena = Bump::new();
+        let mut env = env(config(), &arena, 2);
+        let (a, b) = (env.agents[0].0.to_string(), env.agents[1].0.to_string());
+        let suppress = Sample::OneOf(spaces::SUPPRESS, Box::new(Sample::Discrete(0)));
+        let idle = Sample::OneOf(spaces::IDLE, Box::new(Sample::Discrete(0)));
+
+        let results = env
+            .step_batch(HashMap::from([
+                (
+                    a.clone(),
+                    SampleBatch::Samples(vec![suppress.clone(), idle.clone(), idle.clone()]),
+                ),
+                (
+                    b.clone(),
+                    SampleBatch::Samples(vec![idle.clone(), idle.clone(), suppress]),
+                ),
+            ]))
+            .unwrap();
+        let rewards: Vec<(f32, f32)> = results
+            .iter()
+            .map(|result| (result.rewards[&a], result.rewards[&b]))
+            .collect();
+        // power 3 * (1 + equipment 1)
+        assert_eq!(rewards, [(6.0, 0.0), (0.0, 0.0), (0.0, 6.0)]);
+        for (env_idx, result) in results.iter().enumerate() {
+            assert_eq!(result.observations[&a], env.observe(&a).unwrap()[env_idx]);
+            assert!(result.final_observations.is_none());
+            assert!(!result.terminated[&a] && !result.truncated[&b]);
+        }
+
+        let before = env.snapshot();
+        let error = env
+            .step_batch(HashMap::from([(
+                "not-an-agent".to_string(),
+                SampleBatch::Samples(vec![idle.clone(); 3]),
+            )]))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<WildfireError>(),
+            Some(WildfireError::UnknownAgent(agent)) if agent == "not-an-agent"
+        ));
+        assert!(
+            env.step_batch(HashMap::from([(a, SampleBatch::Samples(vec![idle; 2]))]))
+                .is_err()
+        );
+        assert_eq!(env.snapshot(), before);
+    }
+
+    #[test]
+    fn step_reports_rewards_infos_and_truncation() {
+        let arena = Bump::new();
+        let config = WildfireConfiguration {
+            max_steps: Some(2),
+            ..config()
+        };
+        let mut env = env(config, &arena, 2);
+        let (a, b) = (env.agents[0].0.to_string(), env.agents[1].0.to_string());
+        let suppress = Sample::OneOf(spaces::SUPPRESS, Box::new(Sample::Discrete(0)));
+        let idle = Sample::OneOf(spaces::IDLE, Box::new(Sample::Discrete(0)));
+
+        let result = env
+            .step(&HashMap::from([(
+                a.clone(),
+                vec![suppress.clone(), idle.clone(), suppress],
+            )]))
+            .unwrap();
+        // power 3 * (1 + equipment 1)
+        assert_eq!(result.rewards[&a], [6.0, 0.0, 6.0]);
+        assert_eq!(result.rewards[&b], [0.0, 0.0, 0.0]);
+        for env_idx in 0..3 {
+            let (start, end) = env.state.env.offsets[env_idx];
+            let burned_out = *env.outputs[env_idx][transitions::BURNED_OUT]
+                .downcast_ref::<usize>()
+                .unwrap();
+            for agent in [&a, &b] {
+                let info = &result.infos[agent][env_idx];
+                assert_eq!(info.len(), 3);
+                assert_eq!(info["suppressed"], result.rewards[agent][env_idx]);
+                assert_eq!(info["fires"], (end - start) as f32);
+                assert_eq!(info["burned_out"], burned_out as f32);
+            }
+        }
+        assert_eq!(result.truncated[&a], [false; 3]);
+        assert_eq!(result.truncated[&b], [false; 3]);
+
+        let result = env.step(&HashMap::new()).unwrap();
+        assert_eq!(result.rewards[&a], [0.0; 3]);
+        assert_eq!(result.truncated[&a], [true; 3]);
+        assert_eq!(result.truncated[&b], [true; 3]);
+    }
+
+    #[test]
+    fn sampled_batches_step_like_samples() {
+        let arena = Bump::new();
+        let mut env = env(config(), &arena, 4);
+        let fork_arena = Bump::new();
+        let mut fork = env.fork(&fork_arena);
+
+        let mut rng = StdRng::seed_from_u64(4);
+        let batches: HashMap<String, SampleBatch> = env
+            .agents
+            .iter()
+            .map(|(name, ..)| {
+                let batch = env.action_space.sample_batch(3, &mut rng).unwrap();
+                (name.to_string(), batch)
+            })
+            .collect();
+        let samples = batches
+            .iter()
+            .map(|(name, batch)| (name.clone(), batch.clone().into_samples()))
+            .collect();
+
+        env.step_batch(batches).unwrap();
+        fork.step(&samples).unwrap();
+        assert_eq!(env.snapshot(), fork.snapshot());
+    }
+
+    #[test]
+    fn reset_env_repopulates_only_that_environment() {
+        let arena = Bump::new();
+        let mut env = env(config(), &arena, 6);
+        for _ in 0..2 {
+            env.step(&HashMap::new()).unwrap();
+        }
+        let before = env.snapshot().state;
+
+        env.reset_env(1, None).unwrap();
+        let after = env.snapshot().state;
+        assert_eq!(after.env.timestep, [2, 0, 2]);
+        assert_eq!(after.env.offsets[1], (4, 6));
+        assert_eq!(after.agent.offsets[1], (4, 6));
+        assert_eq!(after.env.fuel[16..32], env.config.initial_fuel);
+        for env_idx in [0, 2] {
+            let cells = env_idx * 16..(env_idx + 1) * 16;
+            assert_eq!(after.env.offsets[env_idx], before.env.offsets[env_idx]);
+            assert_eq!(after.env.fuel[cells.clone()], before.env.fuel[cells]);
+        }
+        assert_ne!(before.env.fuel[16..32], env.config.initial_fuel);
+    }
+
+    #[test]
+    fn auto_reset_restarts_finished_environments() {
+        let arena = Bump::new();
+        let config = WildfireConfiguration {
+            auto_reset: true,
+            ..config()
+        };
+        let mut env = env(config, &arena, 3);
+        env.state.env.remove_fires(1, &[0, 1]).unwrap();
+
+        let reference_arena = Bump::new();
+        let mut reference = env.fork(&reference_arena);
+        reference.config.auto_reset = false;
+        reference.step_batch(HashMap::new()).unwrap();
+        let results = env.step_batch(HashMap::new()).unwrap();
+
+        let (stepped, reset) = (reference.snapshot().state, env.snapshot().state);
+        assert_eq!(reset.env.timestep, [1, 0, 1]);
+        assert_eq!(reset.env.offsets[1], (4, 6));
+        assert_eq!(reset.env.fuel[16..32], env.config.initial_fuel);
+        assert_eq!(reset.env.offsets[0], stepped.env.offsets[0]);
+        assert_eq!(reset.env.offsets[2], stepped.env.offsets[2]);
+        assert_eq!(reset.env.fuel[..16], stepped.env.fuel[..16]);
+        assert_eq!(reset.env.fuel[32..], stepped.env.fuel[32..]);
+
+        let agent = env.agents[0].0.to_string();
+        assert!(results[1].terminated[&agent]);
+        assert_eq!(
+            results[1].final_observations.as_ref().unwrap()[&agent],
+            reference.observe(&agent).unwrap()[1]
+        );
+        assert_eq!(
+            results[1].observations[&agent],
+            env.observe(&agent).unwrap()[1]
+        );
+        assert!(results[0].final_observations.is_none() && results[2].final_observations.is_none());
+    }
+
+    #[test]
+    fn reset_env_derives_each_environment_seed() {
+        let arena = Bump::new();
+        let mut env = env(config(), &arena, 4);
+        env.reset_env(0, Some(11)).unwrap();
+        env.reset_env(1, Some(11)).unwrap();
+
+        assert_ne!(env.env_rngs[0], env.env_rngs[1]);
+        for env_idx in 0..2 {
+            assert_eq!(
+                env.env_rngs[env_idx],
+                seeding::transition_rngs(seeding::env_seed(11, env_idx), env.transitions.len())
+            );
+        }
+    }
+
+    #[test]
+    fn restored_environment_steps_like_the_original() {
+        let arena = Bump::new();
+        let mut original = env(config(), &arena, 8);
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..3 {
+            let actions = random_actions(&original, &mut rng);
+            original.step(&actions).unwrap();
+        }
+        let snapshot =
+            WildfireSnapshot::from_bytes(&original.snapshot().to_bytes().unwrap()).unwrap();
+
+        let other_arena = Bump::new();
+        let mut restored = env(config(), &other_arena, 99);
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.snapshot(), original.snapshot());
+        for _ in 0..8 {
+            let actions = random_actions(&original, &mut rng);
+            original.step(&actions).unwrap();
+            restored.step(&actions).unwrap();
+            assert_eq!(restored.snapshot(), original.snapshot());
+        }
+    }
+
+    #[test]
+    fn fork_steps_like_the_original() {
+        let arena = Bump::new();
+        let mut original = env(config(), &arena, 5);
+        let fork_arena = Bump::new();
+        let mut fork = original.fork(&fork_arena);
+        assert_eq!(fork.snapshot(), original.snapshot());
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..8 {
+            let actions = random_actions(&original, &mut rng);
+            original.step(&actions).unwrap();
+            fork.step(&actions).unwrap();
+            assert_eq!(fork.snapshot(), original.snapshot());
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Owned copies of a `WildfireEnvironment`, for resuming runs, branching counterfactuals and
+//! attaching states to bug reports.
+//!
+//! A snapshot holds every buffer of the state (including dead slots past each environment's
+//! offsets) and every random stream, so an environment restored from it steps exactly as the
+//! original would have.
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::wildfire::error::WildfireError;
+use crate::wildfire::seeding::WildfireRng;
+use crate::wildfire::state::{AgentState, EnvState, WildfireState};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WildfireSnapshot {
+    /// Master seed, see `seeding`
+    pub seed: u64,
+    /// Generator agent names are drawn from
+    pub rng: WildfireRng,
+    /// One generator per transition of each environment
+    pub env_rngs: Vec<Vec<WildfireRng>>,
+    /// Agents placed in every environment on reset
+    pub agents: Vec<(Uuid, u8, u8, u8, u8, u8, u8)>,
+
+    pub state: StateSnapshot,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub num_envs: usize,
+    pub env: EnvSnapshot,
+    pub agent: AgentSnapshot,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvSnapshot {
+    pub max_fires: usize,
+    pub offsets: Vec<(usize, usize)>,
+    pub fuel: Vec<u8>,
+    pub timestep: Vec<u32>,
+    pub y: Vec<u8>,
+    pub x: Vec<u8>,
+    pub size: Vec<u16>,
+    pub intensity: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentSnapshot {
+    pub max_agents: usize,
+    pub offsets: Vec<(usize, usize)>,
+    pub name: Vec<Uuid>,
+    pub y: Vec<u8>,
+    pub x: Vec<u8>,
+    pub power: Vec<u8>,
+    pub suppressant: Vec<u8>,
+    pub capacity: Vec<u8>,
+    pub equipment: Vec<u8>,
+}
+
+impl WildfireSnapshot {
+    /// Compact binary encoding.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    /// Human-readable encoding, e.g. for bug reports.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+/// Fails if `src`, from a snapshot taken under another configuration, would not fit in `dst`.
+fn check_buffer<T>(dst: &[T], src: &[T], buffer: &str) -> std::result::Result<(), WildfireError> {
+    if dst.len() != src.len() {
+        return Err(WildfireError::IncompatibleSnapshot(format!(
+            "{buffer} has {} entries, expected {}",
+            src.len(),
+            dst.len()
+        )));
+    }
+    Ok(())
+}
+
+impl WildfireState<'_> {
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            num_envs: self.num_envs,
+            env: self.env.snapshot(),
+            agent: self.agent.snapshot(),
+        }
+    }
+
+    /// Overwrites the state with `snapshot`, which must come from a state of the same shape. On
+    /// failure the state is left untouched.
+    pub fn restore(&mut self, snapshot: &StateSnapshot) -> std::result::Result<(), WildfireError> {
+        if snapshot.num_envs != self.num_envs {
+            return Err(WildfireError::IncompatibleSnapshot(format!(
+                "snapshot has {} environments, expected {}",
+                snapshot.num_envs, self.num_envs
+            )));
+        }
+        self.env.check(&snapshot.env)?;
+        self.agent.check(&snapshot.agent)?;
+
+        self.env.restore(&snapshot.env)?;
+        self.agent.restore(&snapshot.agent)
+    }
+}
+
+impl EnvState<'_> {
+    pub fn snapshot(&self) -> EnvSnapshot {
+        EnvSnapshot {
+            max_fires: self.max_fires,
+            offsets: self.offsets.to_vec(),
+            fuel: self.fuel.to_vec(),
+            timestep: self.timestep.to_vec(),
+            y: self.y.to_vec(),
+            x: self.x.to_vec(),
+            size: self.size.to_vec(),
+            intensity: self.intensity.to_vec(),
+        }
+    }
+
+    /// Fails unless `snapshot` has the shape of this state.
+    fn check(&self, snapshot: &EnvSnapshot) -> std::result::Result<(), WildfireError> {
+        if snapshot.max_fires != self.max_fires {
+            return Err(WildfireError::IncompatibleSnapshot(format!(
+                "snapshot has max_fires {}, expected {}",
+                snapshot.max_fires, self.max_fires
+            )));
+        }
+        check_buffer(self.offsets, &snapshot.offsets, "fire offsets")?;
+        check_buffer(self.fuel, &snapshot.fuel, "fuel")?;
+        check_buffer(self.timestep, &snapshot.timestep, "timestep")?;
+        check_buffer(self.y, &snapshot.y, "fire y")?;
+        check_buffer(self.x, &snapshot.x, "fire x")?;
+        check_buffer(self.size, &snapshot.size, "fire size")?;
+        check_buffer(self.intensity, &snapshot.intensity, "fire intensity")
+    }
+
+    /// Overwrites the fires, fuel and timesteps with `snapshot`, leaving them untouched if it
+    /// does not fit.
+    pub fn restore(&mut self, snapshot: &EnvSnapshot) -> std::result::Result<(), WildfireError> {
+        self.check(snapshot)?;
+        self.offsets.copy_from_slice(&snapshot.offsets);
+        self.fuel.copy_from_slice(&snapshot.fuel);
+        self.timestep.copy_from_slice(&snapshot.timestep);
+        self.y.copy_from_slice(&snapshot.y);
+        self.x.copy_from_slice(&snapshot.x);
+        self.size.copy_from_slice(&snapshot.size);
+        self.intensity.copy_from_slice(&snapshot.intensity);
+        Ok(())
+    }
+}
+
+impl AgentState<'_> {
+    pub fn snapshot(&self) -> AgentSnapshot {
+        AgentSnapshot {
+            max_agents: self.max_agents,
+            offsets: self.offsets.to_vec(),
+            name: self.name.to_vec(),
+            y: self.y.to_vec(),
+            x: self.x.to_vec(),
+            power: self.power.to_vec(),
+            suppressant: self.suppressant.to_vec(),
+            capacity: self.capacity.to_vec(),
+            equipment: self.equipment.to_vec(),
+        }
+    }
+
+    /// Fails unless `snapshot` has the shape of this state.
+    fn check(&self, snapshot: &AgentSnapshot) -> std::result::Result<(), WildfireError> {
+        if snapshot.max_agents != self.max_agents {
+            return Err(WildfireError::IncompatibleSnapshot(format!(
+                "snapshot has max_agents {}, expected {}",
+                snapshot.max_agents, self.max_agents
+            )));
+        }
+        check_buffer(self.offsets, &snapshot.offsets, "agent offsets")?;
+        check_buffer(self.name, &snapshot.name, "agent name")?;
+        check_buffer(self.y, &snapshot.y, "agent y")?;
+        check_buffer(self.x, &snapshot.x, "agent x")?;
+        check_buffer(self.power, &snapshot.power, "agent power")?;
+        check_buffer(self.suppressant, &snapshot.suppressant, "agent suppressant")?;
+        check_buffer(self.capacity, &snapshot.capacity, "agent capacity")?;
+        check_buffer(self.equipment, &snapshot.equipment, "agent equipment")
+    }
+
+    /// Overwrites the agents with `snapshot`, leaving them untouched if it does not fit.
+    pub fn restore(&mut self, snapshot: &AgentSnapshot) -> std::result::Result<(), WildfireError> {
+        self.check(snapshot)?;
+        self.offsets.copy_from_slice(&snapshot.offsets);
+        self.name.copy_from_slice(&snapshot.name);
+        self.y.copy_from_slice(&snapshot.y);
+        self.x.copy_from_slice(&snapshot.x);
+        self.power.copy_from_slice(&snapshot.power);
+        self.suppressant.copy_from_slice(&snapshot.suppressant);
+        self.capacity.copy_from_slice(&snapshot.capacity);
+        self.equipment.copy_from_slice(&snapshot.equipment);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wildfire::config::WildfireConfiguration;
+    use crate::wildfire::seeding;
+    use bumpalo::Bump;
+
+    fn config(num_envs: usize) -> WildfireConfiguration {
+        WildfireConfiguration::test(num_envs, (2, 2))
+            .with_max_agents(2, 2)
+            .with_max_fires(3, 3)
+            .with_fuel(5)
+    }
+
+    #[test]
+    fn state_round_trips_through_bytes_and_json() {
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config(2), &arena);
+        state
+            .env
+            .add_fires(1, &[(0, 1, 40, 2), (1, 1, 7, 1)])
+            .unwrap();
+        state
+            .agent
+            .add_agent(0, &(Uuid::from_u128(9), 1, 0, 2, 3, 4, 0))
+            .unwrap();
+        state.env.fuel[5] = 3;
+        state.env.timestep[1] = 11;
+
+        let snapshot = WildfireSnapshot {
+            seed: 3,
+            rng: seeding::agent_rng(3),
+            env_rngs: vec![seeding::transition_rngs(3, 2)],
+            agents: Vec::new(),
+            state: state.snapshot(),
+        };
+        let from_bytes = WildfireSnapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+        let from_json = WildfireSnapshot::from_json(&snapshot.to_json().unwrap()).unwrap();
+        assert_eq!(from_bytes, snapshot);
+        assert_eq!(from_json, snapshot);
+
+        let other_arena = Bump::new();
+        let mut restored = WildfireState::new(&config(2), &other_arena);
+        restored.restore(&from_bytes.state).unwrap();
+        assert_eq!(restored.snapshot(), snapshot.state);
+        assert_eq!(restored.env.offsets[1], (3, 5));
+        assert_eq!(restored.env.size[4], 7);
+
+        let mut mismatched = WildfireState::new(&config(1), &other_arena);
+        assert!(matches!(
+            mismatched.restore(&snapshot.state),
+            Err(WildfireError::IncompatibleSnapshot(_))
+        ));
+    }
+
+    #[test]
+    fn failed_restore_leaves_state_untouched() {
+        let arena = Bump::new();
+        let mut source = WildfireState::new(&config(2), &arena);
+        source.env.add_fire(0, &(1, 0, 12, 3)).unwrap();
+        source
+            .agent
+            .add_agent(1, &(Uuid::from_u128(4), 0, 1, 1, 1, 1, 1))
+            .unwrap();
+        source.env.fuel.fill(2);
+        let snapshot = source.snapshot();
+
+        let mut short_equipment = snapshot.clone();
+        short_equipment.agent.equipment.pop();
+        let mut short_fuel = snapshot.clone();
+        short_fuel.env.fuel.pop();
+        let mut other_max_agents = snapshot.clone();
+        other_max_agents.agent.max_agents += 1;
+
+        let mut state = WildfireState::new(&config(2), &arena);
+        let pristine = state.snapshot();
+        for bad in [short_equipment, short_fuel, other_max_agents] {
+            assert!(matches!(
+                state.restore(&bad),
+                Err(WildfireError::IncompatibleSnapshot(_))
+            ));
+            assert_eq!(state.snapshot(), pristine);
+        }
+    }
+}
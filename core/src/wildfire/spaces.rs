@@ -0,0 +1,621 @@
+use std::collections::BTreeMap;
+
+use crate::spaces::{
+    BoxSpace, DictSpace, DiscreteSpace, GraphSpace, OneOfSpace, Sample, SequenceSpace, Space,
+};
+use crate::wildfire::config::WildfireConfiguration;
+use crate::wildfire::state::WildfireState;
+
+/// Most fires that can share a single cell, which bounds the local fire list in observations.
+pub fn max_local_fires(config: &WildfireConfiguration) -> usize {
+    config
+        .max_fires_per_space
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .min(config.max_fires)
+}
+
+/// `OneOfSpace` branch of each kind of action.
+pub const IDLE: i32 = 0;
+pub const MOVE: i32 = 1;
+pub const SUPPRESS: i32 = 2;
+pub const REFILL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+    ];
+
+    /// Row and column offset of a single step in this direction.
+    pub fn delta(&self) -> (i16, i16) {
+        match self {
+            Direction::North => (-1, 0),
+            Direction::South => (1, 0),
+            Direction::East => (0, 1),
+            Direction::West => (0, -1),
+        }
+    }
+
+    /// Cell reached by stepping from `(y, x)`, or `None` if it lies outside the grid.
+    pub fn step(&self, grid: (u8, u8), y: u8, x: u8) -> Option<(u8, u8)> {
+        let (dy, dx) = self.delta();
+        let (ny, nx) = (y as i16 + dy, x as i16 + dx);
+        let in_bounds = ny >= 0 && nx >= 0 && ny < grid.0 as i16 && nx < grid.1 as i16;
+        in_bounds.then_some((ny as u8, nx as u8))
+    }
+}
+
+/// Decoded form of a sample from `action_space`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WildfireAction {
+    /// Do nothing.
+    Idle,
+    /// Move one cell; blocked by the grid edge or a full destination cell.
+    Move(Direction),
+    /// Spend one suppressant on the k-th fire in the agent's cell, reducing its size by
+    /// `power * (1 + equipment)` and its intensity by one.
+    Suppress(usize),
+    /// Restore suppressant to capacity.
+    Refill,
+}
+
+impl WildfireAction {
+    /// Decodes a sample, returning a description of the problem if it is malformed.
+    pub fn from_sample(sample: &Sample, max_local_fires: usize) -> Result<Self, String> {
+        let Sample::OneOf(branch, inner) = sample else {
+            return Err(format!("expected a OneOf sample, got {sample:?}"));
+        };
+        let Sample::Discrete(value) = **inner else {
+            return Err(format!(
+                "expected a Discrete sample in branch {branch}, got {inner:?}"
+            ));
+        };
+
+        match (*branch, value) {
+            (IDLE, 0) => Ok(WildfireAction::Idle),
+            (MOVE, 0..=3) => Ok(WildfireAction::Move(Direction::ALL[value as usize])),
+            (SUPPRESS, k) if k >= 0 && (k as usize) < max_local_fires => {
+                Ok(WildfireAction::Suppress(k as usize))
+            }
+            (REFILL, 0) => Ok(WildfireAction::Refill),
+            (IDLE..=REFILL, _) => Err(format!("value {value} out of range for branch {branch}")),
+            _ => Err(format!("unknown action branch {branch}")),
+        }
+    }
+
+    /// Whether the agent in slot `agent_idx` of environment `env_idx` can take this action.
+    pub fn is_legal(
+        &self,
+        state: &WildfireState,
+        config: &WildfireConfiguration,
+        env_idx: usize,
+        agent_idx: usize,
+    ) -> bool {
+        let agent = &state.agent;
+        let (y, x) = (agent.y[agent_idx], agent.x[agent_idx]);
+        match self {
+            WildfireAction::Idle => true,
+            WildfireAction::Move(direction) => {
+                let Some((ny, nx)) = direction.step(config.grid, y, x) else {
+                    return false;
+                };
+                let (start, end) = agent.offsets[env_idx];
+                let occupants = (start..end)
+                    .filter(|&i| agent.y[i] == ny && agent.x[i] == nx)
+                    .count();
+                occupants
+                    < config.max_agents_per_space
+                        [ny as usize * config.grid.1 as usize + nx as usize]
+            }
+            WildfireAction::Suppress(target) => {
+                let (start, end) = state.env.offsets[env_idx];
+                let fires = (start..end)
+                    .filter(|&i| state.env.y[i] == y && state.env.x[i] == x)
+                    .count();
+                agent.suppressant[agent_idx] > 0 && *target < fires
+            }
+            WildfireAction::Refill => agent.suppressant[agent_idx] < agent.capacity[agent_idx],
+        }
+    }
+}
+
+/// Marks which samples of `space.enumerate()` are legal for the agent in slot `agent_idx` of
+/// environment `env_idx`.
+pub fn action_mask(
+    space: &OneOfSpace,
+    state: &WildfireState,
+    config: &WildfireConfiguration,
+    env_idx: usize,
+    agent_idx: usize,
+) -> Vec<bool> {
+    let max_local_fires = max_local_fires(config);
+    space
+        .enumerate_iter()
+        .into_iter()
+        .flatten()
+        .map(|sample| {
+            WildfireAction::from_sample(&sample, max_local_fires)
+                .is_ok_and(|action| action.is_legal(state, config, env_idx, agent_idx))
+        })
+        .collect()
+}
+
+/// Action space shared by every agent: one of idle, move (north, south, east, west), suppress
+/// the k-th fire in the agent's cell, or refill.
+pub fn action_space(config: &WildfireConfiguration) -> OneOfSpace {
+    let discrete = |n: usize| {
+        Box::new(DiscreteSpace {
+            n: n as i32,
+            start: 0,
+        }) as Box<dyn Space>
+    };
+    OneOfSpace {
+        spaces: vec![
+            discrete(1),
+            discrete(Direction::ALL.len()),
+            discrete(max_local_fires(config)),
+            discrete(1),
+        ],
+    }
+}
+
+/// Observation space shared by every agent.
+///
+/// - `agent`: own `(y, x, power, suppressant, capacity, equipment)`
+/// - `fuel`: remaining fuel per cell
+/// - `fire_size`: total fire size per cell, saturating at `u16::MAX`
+/// - `fire_intensity`: highest fire intensity per cell
+/// - `local_fires`: `(size, intensity)` of each fire in the agent's cell
+pub fn observation_space(config: &WildfireConfiguration) -> DictSpace {
+    let grid_len = config.grid.0 as usize * config.grid.1 as usize;
+    let u8_max = u8::MAX as i32;
+    let u16_max = u16::MAX as i32;
+
+    let mut spaces: BTreeMap<String, Box<dyn Space>> = BTreeMap::new();
+    spaces.insert(
+        "agent".to_string(),
+        Box::new(BoxSpace {
+            low: vec![0; 6],
+            high: vec![
+                config.grid.0 as i32 - 1,
+                config.grid.1 as i32 - 1,
+                u8_max,
+                u8_max,
+                u8_max,
+                u8_max,
+            ],
+        }),
+    );
+    spaces.insert(
+        "fuel".to_string(),
+        Box::new(BoxSpace {
+            low: vec![0; grid_len],
+            high: vec![u8_max; grid_len],
+        }),
+    );
+    spaces.insert(
+        "fire_size".to_string(),
+        Box::new(BoxSpace {
+            low: vec![0; grid_len],
+            high: vec![u16_max; grid_len],
+        }),
+    );
+    spaces.insert(
+        "fire_intensity".to_string(),
+        Box::new(BoxSpace {
+            low: vec![0; grid_len],
+            high: vec![u8_max; grid_len],
+        }),
+    );
+    spaces.insert(
+        "local_fires".to_string(),
+        Box::new(SequenceSpace {
+            inner: Box::new(BoxSpace {
+                low: vec![0, 0],
+                high: vec![u16_max, u8_max],
+            }),
+            max_len: max_local_fires(config),
+        }),
+    );
+
+    DictSpace { spaces }
+}
+
+/// Encodes the observation of the agent in slot `agent_idx` of environment `env_idx`.
+pub fn observe(
+    state: &WildfireState,
+    config: &WildfireConfiguration,
+    env_idx: usize,
+    agent_idx: usize,
+) -> Sample {
+    let grid_len = config.grid.0 as usize * config.grid.1 as usize;
+    let cell = |y: u8, x: u8| y as usize * config.grid.1 as usize + x as usize;
+
+    let agent = &state.agent;
+    let (y, x) = (agent.y[agent_idx], agent.x[agent_idx]);
+    let own = vec![
+        y as i32,
+        x as i32,
+        agent.power[agent_idx] as i32,
+        agent.suppressant[agent_idx] as i32,
+        agent.capacity[agent_idx] as i32,
+        agent.equipment[agent_idx] as i32,
+    ];
+
+    let fuel_start = env_idx * grid_len;
+    let fuel = state.env.fuel[fuel_start..fuel_start + grid_len]
+        .iter()
+        .map(|&f| f as i32)
+        .collect();
+
+    let mut fire_size = vec![0i32; grid_len];
+    let mut fire_intensity = vec![0i32; grid_len];
+    let mut local_fires = Vec::new();
+    let (start, end) = state.env.offsets[env_idx];
+    for fire_idx in start..end {
+        let (fy, fx) = (state.env.y[fire_idx], state.env.x[fire_idx]);
+        let size = state.env.size[fire_idx] as i32;
+        let intensity = state.env.intensity[fire_idx] as i32;
+
+        let idx = cell(fy, fx);
+        fire_size[idx] = (fire_size[idx] + size).min(u16::MAX as i32);
+        fire_intensity[idx] = fire_intensity[idx].max(intensity);
+        if (fy, fx) == (y, x) {
+            local_fires.push(Sample::Box(vec![size, intensity]));
+        }
+    }
+
+    Sample::Dict(BTreeMap::from([
+        ("agent".to_string(), Sample::Box(own)),
+        ("fuel".to_string(), Sample::Box(fuel)),
+        ("fire_size".to_string(), Sample::Box(fire_size)),
+        ("fire_intensity".to_string(), Sample::Box(fire_intensity)),
+        ("local_fires".to_string(), Sample::Sequence(local_fires)),
+    ]))
+}
+
+/// Node kind in graph observations.
+pub const AGENT_NODE: i32 = 0;
+pub const FIRE_NODE: i32 = 1;
+
+/// Edge kind in graph observations.
+pub const NEAR_FIRE: i32 = 0;
+pub const IN_RANGE: i32 = 1;
+
+/// Graph observation space of a whole environment.
+///
+/// Nodes are `(kind, y, x, power or size, suppressant or intensity)`, with every agent before
+/// every fire. Edges run from an agent to each fire within one move (`NEAR_FIRE`) and, in both
+/// directions, between agents within `comm_range` (`IN_RANGE`).
+pub fn graph_observation_space(config: &WildfireConfiguration) -> GraphSpace {
+    GraphSpace {
+        node_space: Box::new(BoxSpace {
+            low: vec![0; 5],
+            high: vec![
+                FIRE_NODE,
+                config.grid.0 as i32 - 1,
+                config.grid.1 as i32 - 1,
+                u16::MAX as i32,
+                u8::MAX as i32,
+            ],
+        }),
+        edge_space: Box::new(DiscreteSpace { n: 2, start: 0 }),
+        max_nodes: config.max_agents + config.max_fires,
+        max_edges: config.max_agents * config.max_fires
+            + config.max_agents * config.max_agents.saturating_sub(1),
+    }
+}
+
+/// Encodes environment `env_idx` as a sample of `graph_observation_space`.
+pub fn observe_graph(
+    state: &WildfireState,
+    config: &WildfireConfiguration,
+    env_idx: usize,
+) -> Sample {
+    let agent = &state.agent;
+    let (agent_start, agent_end) = agent.offsets[env_idx];
+    let (fire_start, fire_end) = state.env.offsets[env_idx];
+    // Widened so distances across grids with sides over 128 do not overflow.
+    let distance = |(ay, ax): (u8, u8), (by, bx): (u8, u8)| {
+        u16::from(ay.abs_diff(by)) + u16::from(ax.abs_diff(bx))
+    };
+
+    let agents: Vec<(u8, u8)> = (agent_start..agent_end)
+        .map(|i| (agent.y[i], agent.x[i]))
+        .collect();
+    let fires: Vec<(u8, u8)> = (fire_start..fire_end)
+        .map(|i| (state.env.y[i], state.env.x[i]))
+        .collect();
+
+    let mut nodes = Vec::with_capacity(agents.len() + fires.len());
+    for (i, &(y, x)) in (agent_start..agent_end).zip(&agents) {
+        nodes.push(Sample::Box(vec![
+            AGENT_NODE,
+            y as i32,
+            x as i32,
+            agent.power[i] as i32,
+            agent.suppressant[i] as i32,
+        ]));
+    }
+    for (i, &(y, x)) in (fire_start..fire_end).zip(&fires) {
+        nodes.push(Sample::Box(vec![
+            FIRE_NODE,
+            y as i32,
+            x as i32,
+            state.env.size[i] as i32,
+            state.env.intensity[i] as i32,
+        ]));
+    }
+
+    let mut edges = Vec::new();
+    let mut edge_index = Vec::new();
+    for (a, &pos) in agents.iter().enumerate() {
+        for (f, &fire) in fires.iter().enumerate() {
+            if distance(pos, fire) <= 1 {
+                edges.push(Sample::Discrete(NEAR_FIRE));
+                edge_index.push((a, agents.len() + f));
+            }
+        }
+        if let Some(range) = config.comm_range {
+            for (b, &other) in agents.iter().enumerate() {
+                if a != b && distance(pos, other) <= u16::from(range) {
+                    edges.push(Sample::Discrete(IN_RANGE));
+                    edge_index.push((a, b));
+                }
+            }
+        }
+    }
+
+    Sample::Graph {
+        nodes,
+        edges,
+        edge_index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wildfire::{seeding, transitions};
+    use bumpalo::Bump;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use uuid::Uuid;
+
+    /// One environment on a 3x3 grid: an agent without suppressant on a fire in a corner, a full
+    /// agent with no fire in the center, and a partly full agent on a fire in the other corner.
+    fn state<'a>(config: &WildfireConfiguration, arena: &'a Bump) -> WildfireState<'a> {
+        let mut state = WildfireState::new(config, arena);
+        state
+            .agent
+            .add_agents(
+                0,
+                &[
+                    (Uuid::from_u128(1), 0, 0, 1, 0, 2, 0),
+                    (Uuid::from_u128(2), 1, 1, 1, 2, 2, 0),
+                    (Uuid::from_u128(3), 2, 2, 1, 1, 3, 0),
+                ],
+            )
+            .unwrap();
+        state
+            .env
+            .add_fires(0, &[(0, 0, 5, 5), (2, 2, 5, 5)])
+            .unwrap();
+        state
+    }
+
+    fn action(branch: i32, value: i32) -> Sample {
+        Sample::OneOf(branch, Box::new(Sample::Discrete(value)))
+    }
+
+    #[test]
+    fn action_mask_lines_up_with_enumeration() {
+        let config = WildfireConfiguration::test(1, (3, 3)).with_max_fires(4, 2);
+        let arena = Bump::new();
+        let state = state(&config, &arena);
+        let space = action_space(&config);
+        let samples = space.enumerate().unwrap();
+
+        let masks: Vec<Vec<bool>> = (0..3)
+            .map(|agent_idx| action_mask(&space, &state, &config, 0, agent_idx))
+            .collect();
+        for (agent_idx, mask) in masks.iter().enumerate() {
+            assert_eq!(mask.len(), samples.len());
+            for (sample, &legal) in samples.iter().zip(mask) {
+                let action = WildfireAction::from_sample(sample, 2).unwrap();
+                assert_eq!(legal, action.is_legal(&state, &config, 0, agent_idx));
+            }
+        }
+
+        // idle, north, south, east, west, suppress 0, suppress 1, refill
+        let (t, f) = (true, false);
+        // No suppressant: suppress is masked even on a fire.
+        assert_eq!(masks[0], [t, f, t, t, f, f, f, t]);
+        // No local fire and at capacity: suppress and refill are masked.
+        assert_eq!(masks[1], [t, t, t, t, t, f, f, f]);
+        // Only one local fire to target.
+        assert_eq!(masks[2], [t, t, f, f, t, t, f, t]);
+    }
+
+    #[test]
+    fn masked_sampling_only_returns_legal_actions() {
+        let config = WildfireConfiguration::test(1, (3, 3)).with_max_fires(4, 2);
+        let arena = Bump::new();
+        let state = state(&config, &arena);
+        let space = action_space(&config);
+        let samples = space.enumerate().unwrap();
+
+        for agent_idx in 0..3 {
+            let mask = action_mask(&space, &state, &config, 0, agent_idx);
+            for seed in 0..64 {
+                let sample = space.sample_masked_with_seed(&mask, seed).unwrap();
+                let idx = samples.iter().position(|s| *s == sample).unwrap();
+                assert!(mask[idx]);
+            }
+        }
+        assert!(space.sample_masked_with_seed(&[false; 8], 0).is_none());
+    }
+
+    #[test]
+    fn observations_conform_to_observation_space() {
+        let config = WildfireConfiguration::test(2, (3, 3)).with_max_fires(6, 2);
+        let arena = Bump::new();
+        let mut state = state(&config, &arena);
+        state.env.fuel.fill(u8::MAX);
+        // Two huge fires in one cell saturate its total size.
+        state
+            .agent
+            .add_agent(1, &(Uuid::from_u128(4), 1, 1, 9, 9, 9, 9))
+            .unwrap();
+        state
+            .env
+            .add_fires(1, &[(1, 1, 60_000, 255), (1, 1, 60_000, 200)])
+            .unwrap();
+
+        let space = observation_space(&config);
+        let actions = action_space(&config);
+        let pipeline = transitions::default_transitions(&config);
+        let mut rngs: Vec<_> = (0..2)
+            .map(|env_idx| seeding::transition_rngs(seeding::env_seed(1, env_idx), pipeline.len()))
+            .collect();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            for env_idx in 0..2 {
+                let (start, end) = state.agent.offsets[env_idx];
+                for agent_idx in start..end {
+                    let observation = observe(&state, &config, env_idx, agent_idx);
+                    assert!(space.contains(&observation), "{observation:?}");
+                }
+            }
+
+            let decoded = (0..2)
+                .map(|env_idx| {
+                    let (start, end) = state.agent.offsets[env_idx];
+                    (start..end)
+                        .map(|_| {
+                            let sample = actions.sample_with_rng(&mut rng).unwrap();
+                            WildfireAction::from_sample(&sample, max_local_fires(&config)).unwrap()
+                        })
+                        .collect()
+                })
+                .collect();
+            transitions::run(&pipeline, &mut state, decoded, &mut rngs, true).unwrap();
+        }
+    }
+
+    #[test]
+    fn graph_links_agents_to_nearby_fires_and_each_other() {
+        let config = WildfireConfiguration {
+            comm_range: Some(2),
+            ..WildfireConfiguration::test(1, (4, 4))
+        };
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        state
+            .agent
+            .add_agents(
+                0,
+                &[
+                    (Uuid::from_u128(1), 0, 0, 1, 2, 2, 0),
+                    (Uuid::from_u128(2), 0, 2, 1, 2, 2, 0),
+                    (Uuid::from_u128(3), 3, 3, 1, 2, 2, 0),
+                ],
+            )
+            .unwrap();
+        state
+            .env
+            .add_fires(0, &[(0, 1, 7, 3), (3, 3, 9, 4), (2, 0, 5, 5)])
+            .unwrap();
+
+        let graph = observe_graph(&state, &config, 0);
+        assert!(graph_observation_space(&config).contains(&graph));
+        let Sample::Graph {
+            nodes,
+            edges,
+            edge_index,
+        } = graph
+        else {
+            panic!("expected a graph, got {graph:?}");
+        };
+        assert_eq!(nodes.len(), 6);
+        assert_eq!(nodes[3], Sample::Box(vec![FIRE_NODE, 0, 1, 7, 3]));
+        assert_eq!(edge_index, [(0, 3), (0, 1), (1, 3), (1, 0), (2, 4)]);
+        assert_eq!(
+            edges,
+            [NEAR_FIRE, IN_RANGE, NEAR_FIRE, IN_RANGE, NEAR_FIRE].map(Sample::Discrete)
+        );
+    }
+
+    #[test]
+    fn graph_distances_do_not_wrap_on_large_grids() {
+        let config = WildfireConfiguration {
+            comm_range: Some(1),
+            ..WildfireConfiguration::test(1, (u8::MAX, u8::MAX))
+        };
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        state
+            .agent
+            .add_agents(
+                0,
+                &[
+                    (Uuid::from_u128(1), 0, 0, 1, 1, 1, 0),
+                    (Uuid::from_u128(2), 128, 129, 1, 1, 1, 0),
+                ],
+            )
+            .unwrap();
+        state.env.add_fire(0, &(128, 129, 1, 1)).unwrap();
+
+        let graph = observe_graph(&state, &config, 0);
+        assert!(graph_observation_space(&config).contains(&graph));
+        // Only the second agent stands next to the fire; (0, 0) is 257 cells from both.
+        assert!(matches!(graph, Sample::Graph { edge_index, .. } if edge_index == [(1, 2)]));
+    }
+
+    #[test]
+    fn from_sample_decodes_each_branch_and_rejects_the_rest() {
+        assert_eq!(
+            WildfireAction::from_sample(&action(IDLE, 0), 2),
+            Ok(WildfireAction::Idle)
+        );
+        assert_eq!(
+            WildfireAction::from_sample(&action(MOVE, 1), 2),
+            Ok(WildfireAction::Move(Direction::South))
+        );
+        assert_eq!(
+            WildfireAction::from_sample(&action(SUPPRESS, 1), 2),
+            Ok(WildfireAction::Suppress(1))
+        );
+        assert_eq!(
+            WildfireAction::from_sample(&action(REFILL, 0), 2),
+            Ok(WildfireAction::Refill)
+        );
+
+        for sample in [
+            action(IDLE, 1),
+            action(MOVE, 4),
+            action(SUPPRESS, 2),
+            action(SUPPRESS, -1),
+            action(REFILL, 1),
+            action(4, 0),
+            action(-1, 0),
+            Sample::Discrete(0),
+            Sample::OneOf(MOVE, Box::new(Sample::Box(vec![0]))),
+        ] {
+            assert!(WildfireAction::from_sample(&sample, 2).is_err());
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use bumpalo::collections::Vec;
 use bumpalo::{Bump, vec};
 use color_eyre::Report;
+use itertools::izip;
 use uuid::Uuid;
 
-use crate::state::{IndexView, State};
+use crate::state::{IndexView, IndexViewMut, State};
 use crate::wildfire::config::WildfireConfiguration;
 use crate::wildfire::error::WildfireError;
 
@@ -23,6 +24,13 @@ pub struct WildfireStateView<'a> {
     pub agent: AgentStateView<'a>,
 }
 
+/// Mutable view of a single environment, disjoint from every other environment's view.
+pub struct WildfireStateViewMut<'s> {
+    pub env_idx: usize,
+    pub env: EnvStateViewMut<'s>,
+    pub agent: AgentStateViewMut<'s>,
+}
+
 impl<'a> WildfireState<'a> {
     pub fn new(config: &WildfireConfiguration, arena: &'a Bump) -> Self {
         let env = EnvState::new(arena, config.num_envs, config.max_fires, config.grid);
@@ -35,6 +43,36 @@ impl<'a> WildfireState<'a> {
     }
 }
 
+impl WildfireState<'_> {
+    /// Deep copy allocated in `arena`, independent of this state.
+    pub fn fork<'b>(&self, arena: &'b Bump) -> WildfireState<'b> {
+        WildfireState {
+            num_envs: self.num_envs,
+            env: self.env.fork(arena),
+            agent: self.agent.fork(arena),
+        }
+    }
+
+    /// Empties a single environment, leaving the others untouched.
+    pub fn clear_env(&mut self, env_idx: usize) {
+        self.env.clear_env(env_idx);
+        self.agent.clear_env(env_idx);
+    }
+}
+
+/// Splits `slice` into `count` consecutive chunks of `len` elements.
+fn split_chunks<T>(
+    mut slice: &mut [T],
+    len: usize,
+    count: usize,
+) -> impl Iterator<Item = &mut [T]> {
+    (0..count).map(move |_| {
+        let (head, tail) = std::mem::take(&mut slice).split_at_mut(len);
+        slice = tail;
+        head
+    })
+}
+
 impl<'a> State<'a> for WildfireState<'a> {
     type Config = WildfireConfiguration;
 
@@ -59,6 +97,37 @@ impl<'a> IndexView<'a> for WildfireState<'a> {
     }
 }
 
+impl IndexViewMut for WildfireState<'_> {
+    type ViewMut<'s>
+        = WildfireStateViewMut<'s>
+    where
+        Self: 's;
+
+    fn index_view_mut(&mut self, env_idx: usize) -> Self::ViewMut<'_> {
+        WildfireStateViewMut {
+            env_idx,
+            env: self.env.index_view_mut(env_idx),
+            agent: self.agent.index_view_mut(env_idx),
+        }
+    }
+
+    /// Splits the state into one mutable view per environment, so environments can be stepped
+    /// independently (and in parallel).
+    fn split_envs_mut(&mut self) -> std::vec::Vec<Self::ViewMut<'_>> {
+        self.env
+            .split_envs_mut()
+            .into_iter()
+            .zip(self.agent.split_envs_mut())
+            .enumerate()
+            .map(|(env_idx, (env, agent))| WildfireStateViewMut {
+                env_idx,
+                env,
+                agent,
+            })
+            .collect()
+    }
+}
+
 impl<'a> From<&'a WildfireState<'a>> for WildfireStateView<'a> {
     fn from(state: &'a WildfireState) -> Self {
         WildfireStateView {
@@ -73,6 +142,8 @@ pub struct EnvState<'a> {
     pub offsets: &'a mut [(usize, usize)],
 
     pub fuel: &'a mut [u8],
+    /// Number of steps taken in each environment since its last reset
+    pub timestep: &'a mut [u32],
 
     pub y: &'a mut [u8],
     pub x: &'a mut [u8],
@@ -87,6 +158,76 @@ pub struct EnvStateView<'a> {
     pub intensity: &'a [u8],
 }
 
+/// Mutable view of one environment's fires, fuel and timestep.
+///
+/// Fire slices span the environment's whole capacity; the first `len()` entries are live, and
+/// indices are relative to the environment.
+pub struct EnvStateViewMut<'s> {
+    pub max_fires: usize,
+    pub offset: &'s mut (usize, usize),
+
+    pub fuel: &'s mut [u8],
+    pub timestep: &'s mut u32,
+
+    pub y: &'s mut [u8],
+    pub x: &'s mut [u8],
+    pub size: &'s mut [u16],
+    pub intensity: &'s mut [u8],
+}
+
+impl EnvStateViewMut<'_> {
+    /// Number of live fires.
+    pub fn len(&self) -> usize {
+        self.offset.1 - self.offset.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn add_fire(&mut self, fire: &(u8, u8, u16, u8)) -> Result<(), WildfireError> {
+        let end = self.len();
+        if end >= self.max_fires {
+            return Err(WildfireError::FireCapacityExceeded {
+                attempted: end + 1,
+                max: self.max_fires,
+            });
+        }
+        let (y, x, size, intensity) = *fire;
+        self.y[end] = y;
+        self.x[end] = x;
+        self.size[end] = size;
+        self.intensity[end] = intensity;
+        self.offset.1 += 1;
+        Ok(())
+    }
+
+    pub fn remove_fires(&mut self, indices: &[usize]) -> Result<(), WildfireError> {
+        let mut sorted = indices.to_vec();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        for &idx in &sorted {
+            self.remove_fire(idx)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove_fire(&mut self, remove_idx: usize) -> Result<(), WildfireError> {
+        let len = self.len();
+        if remove_idx >= len {
+            return Err(WildfireError::FireIndexOutOfBounds(remove_idx));
+        }
+        let last_idx = len - 1;
+        if remove_idx != last_idx {
+            self.y.swap(remove_idx, last_idx);
+            self.x.swap(remove_idx, last_idx);
+            self.size.swap(remove_idx, last_idx);
+            self.intensity.swap(remove_idx, last_idx);
+        }
+        self.offset.1 -= 1;
+        Ok(())
+    }
+}
+
 impl<'a> EnvState<'a> {
     pub fn new(arena: &'a Bump, num_envs: usize, max_fires: usize, grid: (u8, u8)) -> Self {
         let mut offsets = Vec::with_capacity_in(num_envs, arena);
@@ -104,10 +245,12 @@ impl<'a> EnvState<'a> {
 
         let grid_len = grid.0 as usize * grid.1 as usize;
         let fuel = vec![in arena; 0u8; num_envs * grid_len].into_bump_slice_mut();
+        let timestep = vec![in arena; 0u32; num_envs].into_bump_slice_mut();
 
         EnvState {
             offsets,
             max_fires,
+            timestep,
             size,
             intensity,
             y,
@@ -116,11 +259,33 @@ impl<'a> EnvState<'a> {
         }
     }
 
+    /// Deep copy allocated in `arena`.
+    pub fn fork<'b>(&self, arena: &'b Bump) -> EnvState<'b> {
+        EnvState {
+            max_fires: self.max_fires,
+            offsets: arena.alloc_slice_copy(self.offsets),
+            fuel: arena.alloc_slice_copy(self.fuel),
+            timestep: arena.alloc_slice_copy(self.timestep),
+            y: arena.alloc_slice_copy(self.y),
+            x: arena.alloc_slice_copy(self.x),
+            size: arena.alloc_slice_copy(self.size),
+            intensity: arena.alloc_slice_copy(self.intensity),
+        }
+    }
+
     pub fn clear(&mut self) {
         for (i, offset) in self.offsets.iter_mut().enumerate() {
             let start = i * self.max_fires;
             *offset = (start, start);
         }
+        self.timestep.fill(0);
+    }
+
+    /// Removes every fire from environment `env_idx` and restarts its step count.
+    pub fn clear_env(&mut self, env_idx: usize) {
+        let start = env_idx * self.max_fires;
+        self.offsets[env_idx] = (start, start);
+        self.timestep[env_idx] = 0;
     }
 
     pub fn add_fires(
@@ -139,44 +304,15 @@ impl<'a> EnvState<'a> {
         env_idx: usize,
         fire: &(u8, u8, u16, u8),
     ) -> Result<(), WildfireError> {
-        let (start, end) = self.offsets[env_idx];
-        if end >= start + self.max_fires {
-            return Err(WildfireError::FireCapacityExceeded {
-                attempted: end - start + 1,
-                max: self.max_fires,
-            });
-        }
-        let (y, x, size, intensity) = *fire;
-        self.y[end] = y;
-        self.x[end] = x;
-        self.size[end] = size;
-        self.intensity[end] = intensity;
-        self.offsets[env_idx].1 += 1;
-        Ok(())
+        self.index_view_mut(env_idx).add_fire(fire)
     }
 
     pub fn remove_fires(&mut self, env_idx: usize, indices: &[usize]) -> Result<(), WildfireError> {
-        let mut sorted = indices.to_vec();
-        sorted.sort_unstable_by(|a, b| b.cmp(a));
-        for &idx in &sorted {
-            self.remove_fire(env_idx, idx)?;
-        }
-        Ok(())
+        self.index_view_mut(env_idx).remove_fires(indices)
     }
 
     pub fn remove_fire(&mut self, env_idx: usize, remove_idx: usize) -> Result<(), WildfireError> {
-        let (start, end) = self.offsets[env_idx];
-        if remove_idx >= end - start {
-            return Err(WildfireError::FireIndexOutOfBounds(remove_idx));
-        }
-        let last_idx = end - 1;
-        if remove_idx != last_idx {
-            self.size.swap(start + remove_idx, last_idx);
-            self.intensity.swap(start + remove_idx, last_idx);
-            self.fuel.swap(start + remove_idx, last_idx);
-        }
-        self.offsets[env_idx].1 -= 1;
-        Ok(())
+        self.index_view_mut(env_idx).remove_fire(remove_idx)
     }
 }
 
@@ -194,6 +330,56 @@ impl<'a> IndexView<'a> for EnvState<'a> {
     }
 }
 
+impl IndexViewMut for EnvState<'_> {
+    type ViewMut<'s>
+        = EnvStateViewMut<'s>
+    where
+        Self: 's;
+
+    fn index_view_mut(&mut self, env_idx: usize) -> Self::ViewMut<'_> {
+        let grid_len = self.fuel.len() / self.offsets.len();
+        let fires = env_idx * self.max_fires..(env_idx + 1) * self.max_fires;
+        EnvStateViewMut {
+            max_fires: self.max_fires,
+            offset: &mut self.offsets[env_idx],
+            fuel: &mut self.fuel[env_idx * grid_len..(env_idx + 1) * grid_len],
+            timestep: &mut self.timestep[env_idx],
+            y: &mut self.y[fires.clone()],
+            x: &mut self.x[fires.clone()],
+            size: &mut self.size[fires.clone()],
+            intensity: &mut self.intensity[fires],
+        }
+    }
+
+    fn split_envs_mut(&mut self) -> std::vec::Vec<Self::ViewMut<'_>> {
+        let num_envs = self.offsets.len();
+        let grid_len = self.fuel.len().checked_div(num_envs).unwrap_or(0);
+        let max_fires = self.max_fires;
+        izip!(
+            self.offsets.iter_mut(),
+            split_chunks(self.fuel, grid_len, num_envs),
+            self.timestep.iter_mut(),
+            split_chunks(self.y, max_fires, num_envs),
+            split_chunks(self.x, max_fires, num_envs),
+            split_chunks(self.size, max_fires, num_envs),
+            split_chunks(self.intensity, max_fires, num_envs),
+        )
+        .map(
+            |(offset, fuel, timestep, y, x, size, intensity)| EnvStateViewMut {
+                max_fires,
+                offset,
+                fuel,
+                timestep,
+                y,
+                x,
+                size,
+                intensity,
+            },
+        )
+        .collect()
+    }
+}
+
 impl<'a> From<&'a EnvState<'a>> for EnvStateView<'a> {
     fn from(state: &'a EnvState) -> Self {
         EnvStateView {
@@ -230,6 +416,87 @@ pub struct AgentStateView<'a> {
     pub equipment: &'a [u8],
 }
 
+/// Mutable view of one environment's agents.
+///
+/// Slices span the environment's whole capacity; the first `len()` entries are live, and indices
+/// are relative to the environment.
+pub struct AgentStateViewMut<'s> {
+    pub max_agents: usize,
+    pub offset: &'s mut (usize, usize),
+
+    pub name: &'s mut [Uuid],
+
+    pub y: &'s mut [u8],
+    pub x: &'s mut [u8],
+
+    pub power: &'s mut [u8],
+    pub suppressant: &'s mut [u8],
+    pub capacity: &'s mut [u8],
+    pub equipment: &'s mut [u8],
+}
+
+impl AgentStateViewMut<'_> {
+    /// Number of live agents.
+    pub fn len(&self) -> usize {
+        self.offset.1 - self.offset.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn add_agent(
+        &mut self,
+        agent: &(Uuid, u8, u8, u8, u8, u8, u8),
+    ) -> Result<(), WildfireError> {
+        let end = self.len();
+        if end >= self.max_agents {
+            return Err(WildfireError::AgentCapacityExceeded {
+                attempted: end + 1,
+                max: self.max_agents,
+            });
+        }
+        let (name, y, x, power, suppressant, capacity, equipment) = *agent;
+        self.name[end] = name;
+        self.y[end] = y;
+        self.x[end] = x;
+        self.power[end] = power;
+        self.suppressant[end] = suppressant;
+        self.capacity[end] = capacity;
+        self.equipment[end] = equipment;
+        self.offset.1 += 1;
+        Ok(())
+    }
+
+    pub fn remove_agents(&mut self, indices: &[usize]) -> Result<(), WildfireError> {
+        let mut sorted = indices.to_vec();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        for &idx in &sorted {
+            self.remove_agent(idx)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove_agent(&mut self, remove_idx: usize) -> Result<(), WildfireError> {
+        let len = self.len();
+        if remove_idx >= len {
+            return Err(WildfireError::AgentIndexOutOfBounds(remove_idx));
+        }
+        let last_idx = len - 1;
+        if remove_idx != last_idx {
+            self.name.swap(remove_idx, last_idx);
+            self.y.swap(remove_idx, last_idx);
+            self.x.swap(remove_idx, last_idx);
+            self.power.swap(remove_idx, last_idx);
+            self.suppressant.swap(remove_idx, last_idx);
+            self.capacity.swap(remove_idx, last_idx);
+            self.equipment.swap(remove_idx, last_idx);
+        }
+        self.offset.1 -= 1;
+        Ok(())
+    }
+}
+
 impl<'a> AgentState<'a> {
     pub fn new(arena: &'a Bump, num_envs: usize, max_agents: usize) -> Self {
         let mut offsets = Vec::with_capacity_in(num_envs, arena);
@@ -261,6 +528,21 @@ impl<'a> AgentState<'a> {
         }
     }
 
+    /// Deep copy allocated in `arena`.
+    pub fn fork<'b>(&self, arena: &'b Bump) -> AgentState<'b> {
+        AgentState {
+            max_agents: self.max_agents,
+            offsets: arena.alloc_slice_copy(self.offsets),
+            name: arena.alloc_slice_copy(self.name),
+            y: arena.alloc_slice_copy(self.y),
+            x: arena.alloc_slice_copy(self.x),
+            power: arena.alloc_slice_copy(self.power),
+            suppressant: arena.alloc_slice_copy(self.suppressant),
+            capacity: arena.alloc_slice_copy(self.capacity),
+            equipment: arena.alloc_slice_copy(self.equipment),
+        }
+    }
+
     pub fn clear(&mut self) {
         for (i, offset) in self.offsets.iter_mut().enumerate() {
             let start = i * self.max_agents;
@@ -268,6 +550,12 @@ impl<'a> AgentState<'a> {
         }
     }
 
+    /// Removes every agent from environment `env_idx`.
+    pub fn clear_env(&mut self, env_idx: usize) {
+        let start = env_idx * self.max_agents;
+        self.offsets[env_idx] = (start, start);
+    }
+
     pub fn add_agents(
         &mut self,
         env_idx: usize,
@@ -284,23 +572,7 @@ impl<'a> AgentState<'a> {
         env_idx: usize,
         agent: &(Uuid, u8, u8, u8, u8, u8, u8),
     ) -> Result<(), WildfireError> {
-        let (start, end) = self.offsets[env_idx];
-        if end >= start + self.max_agents {
-            return Err(WildfireError::AgentCapacityExceeded {
-                attempted: end - start + 1,
-                max: self.max_agents,
-            });
-        }
-        let (name, y, x, power, suppressant, capacity, equipment) = *agent;
-        self.name[end] = name;
-        self.y[end] = y;
-        self.x[end] = x;
-        self.power[end] = power;
-        self.suppressant[end] = suppressant;
-        self.capacity[end] = capacity;
-        self.equipment[end] = equipment;
-        self.offsets[env_idx].1 += 1;
-        Ok(())
+        self.index_view_mut(env_idx).add_agent(agent)
     }
 
     pub fn remove_agents(
@@ -308,31 +580,11 @@ impl<'a> AgentState<'a> {
         env_idx: usize,
         indices: &[usize],
     ) -> Result<(), WildfireError> {
-        let mut sorted = indices.to_vec();
-        sorted.sort_unstable_by(|a, b| b.cmp(a));
-        for &idx in &sorted {
-            self.remove_agent(env_idx, idx)?;
-        }
-        Ok(())
+        self.index_view_mut(env_idx).remove_agents(indices)
     }
 
     pub fn remove_agent(&mut self, env_idx: usize, remove_idx: usize) -> Result<(), WildfireError> {
-        let (start, end) = self.offsets[env_idx];
-        if remove_idx >= end - start {
-            return Err(WildfireError::AgentIndexOutOfBounds(remove_idx));
-        }
-        let last_idx = end - 1;
-        if remove_idx != last_idx {
-            self.name.swap(start + remove_idx, last_idx);
-            self.y.swap(start + remove_idx, last_idx);
-            self.x.swap(start + remove_idx, last_idx);
-            self.power.swap(start + remove_idx, last_idx);
-            self.suppressant.swap(start + remove_idx, last_idx);
-            self.capacity.swap(start + remove_idx, last_idx);
-            self.equipment.swap(start + remove_idx, last_idx);
-        }
-        self.offsets[env_idx].1 -= 1;
-        Ok(())
+        self.index_view_mut(env_idx).remove_agent(remove_idx)
     }
 }
 
@@ -353,6 +605,57 @@ impl<'a> IndexView<'a> for AgentState<'a> {
     }
 }
 
+impl IndexViewMut for AgentState<'_> {
+    type ViewMut<'s>
+        = AgentStateViewMut<'s>
+    where
+        Self: 's;
+
+    fn index_view_mut(&mut self, env_idx: usize) -> Self::ViewMut<'_> {
+        let agents = env_idx * self.max_agents..(env_idx + 1) * self.max_agents;
+        AgentStateViewMut {
+            max_agents: self.max_agents,
+            offset: &mut self.offsets[env_idx],
+            name: &mut self.name[agents.clone()],
+            y: &mut self.y[agents.clone()],
+            x: &mut self.x[agents.clone()],
+            power: &mut self.power[agents.clone()],
+            suppressant: &mut self.suppressant[agents.clone()],
+            capacity: &mut self.capacity[agents.clone()],
+            equipment: &mut self.equipment[agents],
+        }
+    }
+
+    fn split_envs_mut(&mut self) -> std::vec::Vec<Self::ViewMut<'_>> {
+        let num_envs = self.offsets.len();
+        let max_agents = self.max_agents;
+        izip!(
+            self.offsets.iter_mut(),
+            split_chunks(self.name, max_agents, num_envs),
+            split_chunks(self.y, max_agents, num_envs),
+            split_chunks(self.x, max_agents, num_envs),
+            split_chunks(self.power, max_agents, num_envs),
+            split_chunks(self.suppressant, max_agents, num_envs),
+            split_chunks(self.capacity, max_agents, num_envs),
+            split_chunks(self.equipment, max_agents, num_envs),
+        )
+        .map(
+            |(offset, name, y, x, power, suppressant, capacity, equipment)| AgentStateViewMut {
+                max_agents,
+                offset,
+                name,
+                y,
+                x,
+                power,
+                suppressant,
+                capacity,
+                equipment,
+            },
+        )
+        .collect()
+    }
+}
+
 impl<'a> From<&'a AgentState<'a>> for AgentStateView<'a> {
     fn from(state: &'a AgentState) -> Self {
         AgentStateView {
@@ -366,3 +669,38 @@ impl<'a> From<&'a AgentState<'a>> for AgentStateView<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutable_views_cover_one_environment_each() {
+        let arena = Bump::new();
+        let mut env = EnvState::new(&arena, 3, 2, (1, 2));
+        env.add_fire(1, &(0, 1, 5, 1)).unwrap();
+
+        let mut view = env.index_view_mut(2);
+        view.add_fire(&(0, 0, 8, 2)).unwrap();
+        view.fuel[1] = 4;
+        *view.timestep = 3;
+        assert!(view.add_fire(&(0, 0, 1, 1)).is_ok());
+        assert!(matches!(
+            view.add_fire(&(0, 0, 1, 1)),
+            Err(WildfireError::FireCapacityExceeded { .. })
+        ));
+
+        assert_eq!(env.offsets, [(0, 0), (2, 3), (4, 6)]);
+        assert_eq!(env.size[4], 8);
+        assert_eq!(env.fuel, [0, 0, 0, 0, 0, 4]);
+        assert_eq!(env.timestep, [0, 0, 3]);
+
+        let views = env.split_envs_mut();
+        assert_eq!(
+            views.iter().map(|v| v.len()).collect::<std::vec::Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(views[1].size[0], 5);
+        assert_eq!(views[2].fuel, [0, 4]);
+    }
+}
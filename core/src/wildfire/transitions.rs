@@ -1,4 +1,654 @@
-use crate::transition::Transition;
-use crate::wildfire::state::WildfireState;
+use color_eyre::Result;
+use color_eyre::eyre::eyre;
+use rand::{Rng, RngCore};
+use rayon::prelude::*;
 
-pub type WildfireTransition<'a> = dyn Transition<WildfireState<'a>>;
+use crate::spaces::{OneOfSpace, Sample, Space};
+use crate::state::IndexViewMut;
+use crate::transition::{EnvironmentOutput, Transition};
+use crate::wildfire::config::WildfireConfiguration;
+use crate::wildfire::error::WildfireError;
+use crate::wildfire::seeding::WildfireRng;
+use crate::wildfire::spaces::{Direction, WildfireAction};
+use crate::wildfire::state::{WildfireState, WildfireStateViewMut};
+
+/// A transition applied to one environment at a time.
+pub type WildfireTransition = dyn for<'s> Transition<WildfireStateViewMut<'s>> + Send + Sync;
+
+/// Output key for the decoded action of each agent in the environment (`Vec<WildfireAction>`)
+pub const ACTIONS: &str = "actions";
+/// Output key for the fire size removed by each agent in the environment (`Vec<u16>`)
+pub const SUPPRESSED: &str = "suppressed";
+/// Output key for the number of fires removed from the environment (`usize`)
+pub const BURNED_OUT: &str = "burned_out";
+
+/// Builds the per-tick pipeline: agent actions, then fuel consumption, spread and burnout.
+pub fn default_transitions(config: &WildfireConfiguration) -> Vec<Box<WildfireTransition>> {
+    vec![
+        Box::new(Movement::new(config)),
+        Box::new(Suppression),
+        Box::new(Refill),
+        Box::new(FuelConsumption::new(config)),
+        Box::new(FireSpread::new(config)),
+        Box::new(FireBurnout::new(config)),
+    ]
+}
+
+/// Runs `transitions` over every environment of `state`, starting each from its decoded `actions`
+/// (one per agent, under `ACTIONS`), and returns each environment's outputs. `rngs` holds one
+/// generator per transition of each environment, so no two environments or transitions share
+/// randomness.
+///
+/// Environments never share state or randomness, so stepping them in parallel gives results
+/// identical to stepping them one after another, as done when `sequential` is set.
+pub fn run(
+    transitions: &[Box<WildfireTransition>],
+    state: &mut WildfireState,
+    actions: Vec<Vec<WildfireAction>>,
+    rngs: &mut [Vec<WildfireRng>],
+    sequential: bool,
+) -> Result<Vec<EnvironmentOutput>> {
+    let step_env = |((mut view, rngs), actions): (
+        (WildfireStateViewMut, &mut Vec<WildfireRng>),
+        Vec<WildfireAction>,
+    )| {
+        let mut outputs = EnvironmentOutput::new();
+        outputs.insert(ACTIONS.to_string(), Box::new(actions));
+        for (transition, rng) in transitions.iter().zip(rngs.iter_mut()) {
+            let output = transition.apply(&mut view, &outputs, rng)?;
+            outputs.extend(output);
+        }
+        Ok(outputs)
+    };
+
+    let views = state.split_envs_mut();
+    if sequential {
+        views
+            .into_iter()
+            .zip(rngs.iter_mut())
+            .zip(actions)
+            .map(step_env)
+            .collect()
+    } else {
+        views
+            .into_par_iter()
+            .zip(rngs.par_iter_mut())
+            .zip(actions)
+            .map(step_env)
+            .collect()
+    }
+}
+
+fn cell_index(grid: (u8, u8), y: u8, x: u8) -> usize {
+    y as usize * grid.1 as usize + x as usize
+}
+
+fn neighbors(grid: (u8, u8), y: u8, x: u8) -> impl Iterator<Item = (u8, u8)> {
+    Direction::ALL
+        .into_iter()
+        .filter_map(move |direction| direction.step(grid, y, x))
+}
+
+/// Validates and decodes the action `sample` of `agent`.
+pub fn decode_action(
+    action_space: &OneOfSpace,
+    max_local_fires: usize,
+    agent: &str,
+    sample: &Sample,
+) -> Result<WildfireAction, WildfireError> {
+    action_space
+        .validate(sample)
+        .map_err(|source| WildfireError::InvalidAction {
+            agent: agent.to_string(),
+            source,
+        })?;
+    WildfireAction::from_sample(sample, max_local_fires).map_err(|reason| {
+        WildfireError::MalformedAction {
+            agent: agent.to_string(),
+            reason,
+        }
+    })
+}
+
+fn parsed_actions(outputs: &EnvironmentOutput) -> Result<&[WildfireAction]> {
+    outputs
+        .get(ACTIONS)
+        .and_then(|output| output.downcast_ref::<Vec<WildfireAction>>())
+        .map(Vec::as_slice)
+        .ok_or_else(|| eyre!("actions must be decoded before they are applied"))
+}
+
+/// Moves agents one cell, unless blocked by the grid edge or a full destination cell.
+pub struct Movement {
+    grid: (u8, u8),
+    max_agents_per_space: Vec<usize>,
+}
+
+impl Movement {
+    pub fn new(config: &WildfireConfiguration) -> Self {
+        Movement {
+            grid: config.grid,
+            max_agents_per_space: config.max_agents_per_space.clone(),
+        }
+    }
+}
+
+impl Transition<WildfireStateViewMut<'_>> for Movement {
+    fn apply(
+        &self,
+        state: &mut WildfireStateViewMut<'_>,
+        outputs: &EnvironmentOutput,
+        _rng: &mut dyn RngCore,
+    ) -> Result<EnvironmentOutput> {
+        let actions = parsed_actions(outputs)?;
+        let agent = &mut state.agent;
+        let num_agents = agent.len();
+        for (agent_idx, action) in actions.iter().enumerate() {
+            let WildfireAction::Move(direction) = action else {
+                continue;
+            };
+            let (y, x) = (agent.y[agent_idx], agent.x[agent_idx]);
+            let Some((ny, nx)) = direction.step(self.grid, y, x) else {
+                continue;
+            };
+
+            let occupants = (0..num_agents)
+                .filter(|&i| agent.y[i] == ny && agent.x[i] == nx)
+                .count();
+            if occupants >= self.max_agents_per_space[cell_index(self.grid, ny, nx)] {
+                continue;
+            }
+
+            agent.y[agent_idx] = ny;
+            agent.x[agent_idx] = nx;
+        }
+        Ok(EnvironmentOutput::new())
+    }
+}
+
+/// Agents spend one unit of suppressant to reduce the size of a fire in their cell by
+/// `power * (1 + equipment)` and its intensity by one. Agents without suppressant, or targeting a
+/// fire that does not exist, do nothing.
+pub struct Suppression;
+
+impl Transition<WildfireStateViewMut<'_>> for Suppression {
+    fn apply(
+        &self,
+        state: &mut WildfireStateViewMut<'_>,
+        outputs: &EnvironmentOutput,
+        _rng: &mut dyn RngCore,
+    ) -> Result<EnvironmentOutput> {
+        let actions = parsed_actions(outputs)?;
+        let (agent, env) = (&mut state.agent, &mut state.env);
+        let num_fires = env.len();
+        let mut suppressed = vec![0u16; agent.len()];
+        for (agent_idx, reduced) in suppressed.iter_mut().enumerate() {
+            let WildfireAction::Suppress(target) = actions[agent_idx] else {
+                continue;
+            };
+            if agent.suppressant[agent_idx] == 0 {
+                continue;
+            }
+
+            let (y, x) = (agent.y[agent_idx], agent.x[agent_idx]);
+            let Some(fire_idx) = (0..num_fires)
+                .filter(|&i| env.y[i] == y && env.x[i] == x)
+                .nth(target)
+            else {
+                continue;
+            };
+
+            let strength = (agent.power[agent_idx] as u16)
+                .saturating_mul(1 + agent.equipment[agent_idx] as u16);
+            let reduction = strength.min(env.size[fire_idx]);
+            env.size[fire_idx] -= reduction;
+            env.intensity[fire_idx] = env.intensity[fire_idx].saturating_sub(1);
+            agent.suppressant[agent_idx] -= 1;
+            *reduced = reduction;
+        }
+
+        let mut output = EnvironmentOutput::new();
+        output.insert(SUPPRESSED.to_string(), Box::new(suppressed));
+        Ok(output)
+    }
+}
+
+/// Agents restore their suppressant to capacity.
+pub struct Refill;
+
+impl Transition<WildfireStateViewMut<'_>> for Refill {
+    fn apply(
+        &self,
+        state: &mut WildfireStateViewMut<'_>,
+        outputs: &EnvironmentOutput,
+        _rng: &mut dyn RngCore,
+    ) -> Result<EnvironmentOutput> {
+        let actions = parsed_actions(outputs)?;
+        let agent = &mut state.agent;
+        for (agent_idx, action) in actions.iter().enumerate() {
+            if *action == WildfireAction::Refill {
+                agent.suppressant[agent_idx] = agent.capacity[agent_idx];
+            }
+        }
+        Ok(EnvironmentOutput::new())
+    }
+}
+
+/// Every fire consumes fuel from its cell at a rate equal to its intensity.
+pub struct FuelConsumption {
+    grid: (u8, u8),
+}
+
+impl FuelConsumption {
+    pub fn new(config: &WildfireConfiguration) -> Self {
+        FuelConsumption { grid: config.grid }
+    }
+}
+
+impl Transition<WildfireStateViewMut<'_>> for FuelConsumption {
+    fn apply(
+        &self,
+        state: &mut WildfireStateViewMut<'_>,
+        _outputs: &EnvironmentOutput,
+        _rng: &mut dyn RngCore,
+    ) -> Result<EnvironmentOutput> {
+        let env = &mut state.env;
+        for fire_idx in 0..env.len() {
+            let idx = cell_index(self.grid, env.y[fire_idx], env.x[fire_idx]);
+            env.fuel[idx] = env.fuel[idx].saturating_sub(env.intensity[fire_idx]);
+        }
+        Ok(EnvironmentOutput::new())
+    }
+}
+
+/// Fires ignite unburnt orthogonal neighbors with probability `(intensity / 255) * (fuel / 255)`.
+///
+/// New fires start with size 1 and inherit the intensity of the fire that spawned them. Fires
+/// spawned this tick do not spread until the next one.
+pub struct FireSpread {
+    grid: (u8, u8),
+    max_fires_per_space: Vec<usize>,
+}
+
+impl FireSpread {
+    pub fn new(config: &WildfireConfiguration) -> Self {
+        FireSpread {
+            grid: config.grid,
+            max_fires_per_space: config.max_fires_per_space.clone(),
+        }
+    }
+}
+
+impl Transition<WildfireStateViewMut<'_>> for FireSpread {
+    fn apply(
+        &self,
+        state: &mut WildfireStateViewMut<'_>,
+        _outputs: &EnvironmentOutput,
+        rng: &mut dyn RngCore,
+    ) -> Result<EnvironmentOutput> {
+        let env = &mut state.env;
+        for fire_idx in 0..env.len() {
+            let (y, x) = (env.y[fire_idx], env.x[fire_idx]);
+            let intensity = env.intensity[fire_idx];
+            if intensity == 0 {
+                continue;
+            }
+
+            for (ny, nx) in neighbors(self.grid, y, x) {
+                let num_fires = env.len();
+                if num_fires >= env.max_fires {
+                    break;
+                }
+
+                let space_idx = cell_index(self.grid, ny, nx);
+                let fuel = env.fuel[space_idx];
+                let burning = (0..num_fires).any(|i| env.y[i] == ny && env.x[i] == nx);
+                if fuel == 0 || burning || self.max_fires_per_space[space_idx] == 0 {
+                    continue;
+                }
+
+                let probability =
+                    (intensity as f64 / u8::MAX as f64) * (fuel as f64 / u8::MAX as f64);
+                if rng.gen_bool(probability) {
+                    env.add_fire(&(ny, nx, 1, intensity))?;
+                }
+            }
+        }
+        Ok(EnvironmentOutput::new())
+    }
+}
+
+/// Removes fires that have run out of fuel, size or intensity.
+pub struct FireBurnout {
+    grid: (u8, u8),
+}
+
+impl FireBurnout {
+    pub fn new(config: &WildfireConfiguration) -> Self {
+        FireBurnout { grid: config.grid }
+    }
+}
+
+impl Transition<WildfireStateViewMut<'_>> for FireBurnout {
+    fn apply(
+        &self,
+        state: &mut WildfireStateViewMut<'_>,
+        _outputs: &EnvironmentOutput,
+        _rng: &mut dyn RngCore,
+    ) -> Result<EnvironmentOutput> {
+        let env = &mut state.env;
+        let indices: Vec<usize> = (0..env.len())
+            .filter(|&i| {
+                let fuel = env.fuel[cell_index(self.grid, env.y[i], env.x[i])];
+                fuel == 0 || env.size[i] == 0 || env.intensity[i] == 0
+            })
+            .collect();
+        env.remove_fires(&indices)?;
+
+        let mut output = EnvironmentOutput::new();
+        output.insert(BURNED_OUT.to_string(), Box::new(indices.len()));
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wildfire::{seeding, spaces};
+    use bumpalo::Bump;
+    use rand::SeedableRng;
+    use uuid::Uuid;
+
+    fn config(num_envs: usize, sequential: bool) -> WildfireConfiguration {
+        WildfireConfiguration {
+            sequential,
+            ..WildfireConfiguration::test(num_envs, (6, 6))
+                .with_max_fires(24, 2)
+                .with_fuel(200)
+        }
+    }
+
+    fn populate(state: &mut WildfireState, config: &WildfireConfiguration) {
+        for env_idx in 0..config.num_envs {
+            state
+                .env
+                .add_fires(env_idx, &[(2, 2, 40, 200), (4, 1, 10, 120)])
+                .unwrap();
+            let grid_len = config.initial_fuel.len();
+            state.env.fuel[env_idx * grid_len..(env_idx + 1) * grid_len]
+                .copy_from_slice(&config.initial_fuel);
+        }
+    }
+
+    /// Applies `transition` once to environment 0 of `state`, with `actions` already decoded.
+    fn apply(
+        transition: &WildfireTransition,
+        state: &mut WildfireState,
+        actions: Vec<WildfireAction>,
+    ) -> EnvironmentOutput {
+        let mut outputs = EnvironmentOutput::new();
+        outputs.insert(ACTIONS.to_string(), Box::new(actions));
+        let mut rng = WildfireRng::seed_from_u64(0);
+        transition
+            .apply(&mut state.index_view_mut(0), &outputs, &mut rng)
+            .unwrap()
+    }
+
+    fn output<'o, T: 'static>(outputs: &'o EnvironmentOutput, key: &str) -> &'o T {
+        outputs[key].downcast_ref::<T>().unwrap()
+    }
+
+    #[test]
+    fn movement_is_blocked_by_grid_edge_and_full_cells() {
+        let config = WildfireConfiguration::test(1, (6, 6)).with_max_agents(4, 1);
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        state
+            .agent
+            .add_agents(
+                0,
+                &[
+                    (Uuid::from_u128(1), 0, 0, 1, 1, 1, 0),
+                    (Uuid::from_u128(2), 1, 1, 1, 1, 1, 0),
+                    (Uuid::from_u128(3), 1, 2, 1, 1, 1, 0),
+                    (Uuid::from_u128(4), 3, 3, 1, 1, 1, 0),
+                ],
+            )
+            .unwrap();
+
+        apply(
+            &Movement::new(&config),
+            &mut state,
+            vec![
+                WildfireAction::Move(Direction::North),
+                WildfireAction::Move(Direction::East),
+                WildfireAction::Idle,
+                WildfireAction::Move(Direction::South),
+            ],
+        );
+        let positions: Vec<_> = state.agent.y[..4]
+            .iter()
+            .copied()
+            .zip(state.agent.x[..4].iter().copied())
+            .collect();
+        assert_eq!(positions, [(0, 0), (1, 1), (1, 2), (4, 3)]);
+
+        apply(
+            &Movement::new(&config),
+            &mut state,
+            vec![WildfireAction::Move(Direction::West); 4],
+        );
+        assert_eq!(state.agent.x[..4], [0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn refill_restores_suppressant_to_capacity() {
+        let config = config(1, true);
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        state
+            .agent
+            .add_agents(
+                0,
+                &[
+                    (Uuid::from_u128(1), 0, 0, 1, 1, 4, 0),
+                    (Uuid::from_u128(2), 0, 0, 1, 4, 4, 0),
+                    (Uuid::from_u128(3), 0, 0, 1, 0, 3, 0),
+                ],
+            )
+            .unwrap();
+
+        apply(
+            &Refill,
+            &mut state,
+            vec![
+                WildfireAction::Refill,
+                WildfireAction::Refill,
+                WildfireAction::Idle,
+            ],
+        );
+        assert_eq!(state.agent.suppressant[..3], [4, 4, 0]);
+    }
+
+    #[test]
+    fn decode_action_rejects_bad_samples() {
+        let config = config(1, true);
+        let space = spaces::action_space(&config);
+        let action = |branch, value| Sample::OneOf(branch, Box::new(Sample::Discrete(value)));
+
+        assert_eq!(
+            decode_action(&space, 2, "a", &action(spaces::MOVE, 3)).unwrap(),
+            WildfireAction::Move(Direction::West)
+        );
+        assert_eq!(
+            decode_action(&space, 2, "a", &action(spaces::SUPPRESS, 1)).unwrap(),
+            WildfireAction::Suppress(1)
+        );
+        for sample in [
+            action(4, 0),
+            action(-1, 0),
+            action(spaces::MOVE, 4),
+            action(spaces::IDLE, 1),
+            action(spaces::SUPPRESS, 2),
+            Sample::Discrete(0),
+        ] {
+            assert!(matches!(
+                decode_action(&space, 2, "a", &sample),
+                Err(WildfireError::InvalidAction { agent, .. }) if agent == "a"
+            ));
+        }
+        // A space wider than the decoder allows lets the sample through validation.
+        assert!(matches!(
+            decode_action(&space, 1, "a", &action(spaces::SUPPRESS, 1)),
+            Err(WildfireError::MalformedAction { agent, .. }) if agent == "a"
+        ));
+    }
+
+    #[test]
+    fn suppression_reduces_fires_and_spends_suppressant() {
+        let config = config(1, true);
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        state
+            .env
+            .add_fires(0, &[(2, 2, 40, 200), (2, 2, 4, 9)])
+            .unwrap();
+        state
+            .agent
+            .add_agents(
+                0,
+                &[
+                    (Uuid::from_u128(1), 2, 2, 3, 2, 4, 1),
+                    (Uuid::from_u128(2), 2, 2, 3, 0, 4, 1),
+                    (Uuid::from_u128(3), 2, 2, 10, 1, 4, 0),
+                    (Uuid::from_u128(4), 0, 0, 3, 2, 4, 1),
+                ],
+            )
+            .unwrap();
+
+        let outputs = apply(
+            &Suppression,
+            &mut state,
+            vec![
+                WildfireAction::Suppress(0),
+                WildfireAction::Suppress(0),
+                WildfireAction::Suppress(1),
+                WildfireAction::Suppress(0),
+            ],
+        );
+        // power 3 * (1 + equipment 1), an agent without suppressant, a reduction capped at the
+        // fire's size, and an agent with no fire in its cell.
+        assert_eq!(output::<Vec<u16>>(&outputs, SUPPRESSED), &[6, 0, 4, 0]);
+        assert_eq!(state.env.size[..2], [34, 0]);
+        assert_eq!(state.env.intensity[..2], [199, 8]);
+        assert_eq!(state.agent.suppressant[..4], [1, 0, 0, 2]);
+    }
+
+    #[test]
+    fn fuel_is_consumed_at_fire_intensity() {
+        let config = config(1, true);
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        populate(&mut state, &config);
+        state.env.add_fire(0, &(4, 1, 3, 100)).unwrap();
+
+        apply(&FuelConsumption::new(&config), &mut state, Vec::new());
+        assert_eq!(state.env.fuel[2 * 6 + 2], 0);
+        assert_eq!(state.env.fuel[4 * 6 + 1], 0);
+        assert_eq!(state.env.fuel[0], 200);
+        assert_eq!(state.env.fuel.iter().filter(|&&f| f == 200).count(), 34);
+
+        state.env.fuel[4 * 6 + 1] = 230;
+        state.env.remove_fire(0, 2).unwrap();
+        apply(&FuelConsumption::new(&config), &mut state, Vec::new());
+        assert_eq!(state.env.fuel[4 * 6 + 1], 110);
+    }
+
+    #[test]
+    fn burnout_removes_spent_fires() {
+        let config = config(1, true);
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        populate(&mut state, &config);
+        state
+            .env
+            .add_fires(0, &[(0, 0, 0, 5), (0, 1, 5, 0), (5, 5, 5, 5)])
+            .unwrap();
+        state.env.fuel[5 * 6 + 5] = 0;
+
+        let outputs = apply(&FireBurnout::new(&config), &mut state, Vec::new());
+        assert_eq!(*output::<usize>(&outputs, BURNED_OUT), 3);
+        assert_eq!(state.env.offsets[0], (0, 2));
+        let mut remaining: Vec<_> = state.env.y[..2].iter().zip(&state.env.x[..2]).collect();
+        remaining.sort();
+        assert_eq!(remaining, [(&2, &2), (&4, &1)]);
+    }
+
+    #[test]
+    fn spread_respects_fire_limits() {
+        // Full intensity on full fuel always spreads.
+        let mut config = WildfireConfiguration::test(1, (6, 6))
+            .with_max_fires(8, 1)
+            .with_fuel(u8::MAX);
+        config.max_fires_per_space[6 + 2] = 0;
+        let arena = Bump::new();
+        let mut state = WildfireState::new(&config, &arena);
+        state.env.fuel.copy_from_slice(&config.initial_fuel);
+        state.env.add_fire(0, &(2, 2, 40, u8::MAX)).unwrap();
+
+        apply(&FireSpread::new(&config), &mut state, Vec::new());
+        let mut spawned: Vec<_> = (1..4)
+            .map(|i| (state.env.y[i], state.env.x[i], state.env.size[i]))
+            .collect();
+        spawned.sort();
+        // North of (2, 2) allows no fires; fires spawned this tick do not spread yet.
+        assert_eq!(state.env.offsets[0], (0, 4));
+        assert_eq!(spawned, [(2, 1, 1), (2, 3, 1), (3, 2, 1)]);
+        assert_eq!(state.env.intensity[1..4], [u8::MAX; 3]);
+
+        let capped = config.clone().with_max_fires(2, 1);
+        let mut state = WildfireState::new(&capped, &arena);
+        state.env.fuel.copy_from_slice(&capped.initial_fuel);
+        state.env.add_fire(0, &(2, 2, 40, u8::MAX)).unwrap();
+        apply(&FireSpread::new(&capped), &mut state, Vec::new());
+        assert_eq!(state.env.offsets[0], (0, 2));
+    }
+
+    #[test]
+    fn parallel_run_matches_sequential_run() {
+        let mut results = Vec::new();
+        for sequential in [true, false] {
+            let config = config(64, sequential);
+            let arena = Bump::new();
+            let mut state = WildfireState::new(&config, &arena);
+            populate(&mut state, &config);
+
+            let transitions = default_transitions(&config);
+            let mut rngs: Vec<Vec<WildfireRng>> = (0..config.num_envs)
+                .map(|env_idx| {
+                    seeding::transition_rngs(seeding::env_seed(7, env_idx), transitions.len())
+                })
+                .collect();
+            for _ in 0..10 {
+                run(
+                    &transitions,
+                    &mut state,
+                    vec![Vec::new(); config.num_envs],
+                    &mut rngs,
+                    config.sequential,
+                )
+                .unwrap();
+            }
+
+            results.push((
+                state.env.offsets.to_vec(),
+                state.env.y.to_vec(),
+                state.env.x.to_vec(),
+                state.env.size.to_vec(),
+                state.env.intensity.to_vec(),
+                state.env.fuel.to_vec(),
+            ));
+        }
+        assert_eq!(results[0], results[1]);
+    }
+}